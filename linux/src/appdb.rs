@@ -1,17 +1,56 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
 
 use chacha20poly1305::ChaCha20Poly1305;
 use chacha20poly1305::aead::{Aead, KeyInit};
-use sha1::Sha1;
 use sha2::Sha256;
+use subtle::ConstantTimeEq;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::remote;
+use crate::settings;
+use crate::store::{LocalFiles, Store};
+
+pub type SecretString = Zeroizing<String>;
+
+pub const CURRENT_VERSION: &str = "00000000";
+
+// The version field of the stored header selects how the password is derived into a key.
+struct KdfProfile {
+    version: &'static str,
+    mem_cost: u32,
+    time_cost: u32,
+    lanes: u32,
+}
+
+const KDF_PROFILES: [KdfProfile; 1] = [
+    KdfProfile { version: "00000000", mem_cost: 1048576, time_cost: 2, lanes: 4 },
+];
+
+// Derivations cannot be cancelled, so one at a time keeps an abandoned unlock and its retry from each holding 1 GiB.
+static KDF_GUARD: Mutex<()> = Mutex::new(());
+
+// User keys are never empty, so the sealed map carries the creation time under the empty key.
+const CREATED_KEY: &str = "";
 
 pub struct AppDB {
+    // Seconds since the epoch, sealed with the entries so every copy of the database agrees on it.
+    created: Option<u64>,
     db_enc: String,
     db_id: String,
-    db_map: HashMap<String, String>,
+    // Ordered, so identical entries always serialize to the same bytes and therefore the same nonce.
+    db_map: BTreeMap<String, String>,
+    entry_count: usize,
+    last_save: Option<SystemTime>,
+    last_sync: Option<SystemTime>,
     password: [u8; 32],
+    pending_changes: bool,
+    remote_rollback: Option<(String, String)>,
     revision: String,
+    split_unlock: bool,
+    store: Box<dyn Store>,
     version: String,
 }
 
@@ -19,58 +58,140 @@ impl AppDB {
 
     pub fn new() -> Self {
         AppDB {
+            created: None,
             db_enc: "".to_owned(),
             db_id: "00000000".to_owned(),
-            db_map: HashMap::<String, String>::with_capacity(100),
+            db_map: BTreeMap::new(),
+            entry_count: 0,
+            last_save: None,
+            last_sync: None,
             password: [0; 32],
+            pending_changes: false,
+            remote_rollback: None,
             revision: "00000000".to_owned(),
-            version: "00000000".to_owned(),
+            split_unlock: false,
+            store: Box::new(LocalFiles),
+            version: CURRENT_VERSION.to_owned(),
         }
     }
 
-    pub fn get(&mut self, akey: &String) -> Option<String> {
+    #[cfg(test)]
+    fn with_store(store: impl Store + 'static) -> Self {
+        let mut db = AppDB::new();
+        db.store = Box::new(store);
+        db
+    }
+
+    pub fn get(&mut self, akey: &str) -> Option<SecretString> {
         self.unlock();
-        let raval = self.db_map.get(akey);
-        let mut aval: Option<String> = None;
-        if raval.is_some() {
-            aval = Some(raval.unwrap().to_string());
-        }
+        let aval = self.db_map.get(akey).map(|aval| SecretString::new(aval.to_string()));
         self.lock();
         aval
     }
 
-    pub fn set(&mut self, akey: String, aval: String) {
-        use sha2::Digest;
+    pub fn keys(&mut self) -> Vec<String> {
+        self.unlock();
+        let keys: Vec<String> = self.db_map.keys().cloned().collect();
+        self.lock();
+        keys
+    }
+
+    pub fn set(&mut self, akey: String, aval: SecretString) {
         self.unlock();
         if akey.len() > 0 {
+            self.pending_changes = true;
             if aval.len() > 0 {
-                self.db_map.insert(akey, aval);
+                self.db_map.insert(akey, aval.to_string());
             } else {
                 self.db_map.remove(&akey);
             }
         }
-        let db_map_str = serde_json::to_string(&self.db_map).unwrap();
+        self.seal();
+        self.lock();
+    }
+
+    // Moves the stored value, so renaming never depends on what the form holds. Refused for an empty or missing key.
+    pub fn rename(&mut self, old_akey: &str, akey: String) -> bool {
+        self.unlock();
+        let renamed = !akey.is_empty() && match self.db_map.remove(old_akey) {
+            Some(aval) => {
+                self.db_map.insert(akey, aval);
+                true
+            }
+            None => false,
+        };
+        if renamed {
+            self.pending_changes = true;
+        }
+        self.seal();
+        self.lock();
+        renamed
+    }
+
+    fn seal(&mut self) {
+        use sha2::Digest;
+        let created = self.created.map(|created| created.to_string());
+        let mut sealed_map: BTreeMap<&str, &str> = self.db_map.iter().map(|(akey, aval)| (akey.as_str(), aval.as_str())).collect();
+        if let Some(created) = &created {
+            sealed_map.insert(CREATED_KEY, created);
+        }
+        let db_map_str = Zeroizing::new(serde_json::to_string(&sealed_map).unwrap());
+        drop(sealed_map);
         let pre_prefix = self.version.to_string() + &self.db_id + &self.revision; // 8 + 8 + 8 = 24
         assert_eq!(pre_prefix.len(), 24);
-        let hmac_arg = base64::encode(self.password) + &pre_prefix + &db_map_str;
-        let hmac_pre: [u8; 32] = Sha256::digest(hmac_arg).try_into().unwrap();
-        let hmac: [u8; 32] = Sha256::digest(hmac_pre).try_into().unwrap();
+        let hmac_arg = Zeroizing::new(base64::encode(self.password) + &pre_prefix + &db_map_str);
+        let hmac_pre: Zeroizing<[u8; 32]> = Zeroizing::new(Sha256::digest(hmac_arg.as_bytes()).into());
+        let hmac: [u8; 32] = Sha256::digest(hmac_pre.as_slice()).into();
         let nonce: [u8; 12] = hmac[..12].try_into().unwrap();
         let prefix = pre_prefix + &base64::encode(&nonce); // 24 + 16 = 40
         assert_eq!(prefix.len(), 40);
-        let db_str_enc = prefix + &AppDB::encrypt(db_map_str, self.password, &nonce);
+        let db_str_enc = prefix + &AppDB::encrypt(&db_map_str, self.password, &nonce);
         self.db_enc = db_str_enc;
-        self.lock();
     }
 
-    pub fn set_password(&mut self, raw_password: String) {
-        self.password = AppDB::hash_password(raw_password);
+    pub fn set_password_hash(&mut self, password: &[u8; 32]) {
+        self.password = *password;
+    }
+
+    pub fn verify_password_hash(&self, password: &[u8; 32]) -> bool {
+        self.password.ct_eq(password).into()
+    }
+
+    // Remembers a two-person unlock, so re-authentication asks for both passwords.
+    pub fn set_split_unlock(&mut self, split_unlock: bool) {
+        self.split_unlock = split_unlock;
+    }
+
+    pub fn split_unlock(&self) -> bool {
+        self.split_unlock
     }
 
     pub fn set_db_id(&mut self, raw_dbid: String) {
         assert!(raw_dbid.len() <= 8);
         self.db_id = format!("{:0>8}", raw_dbid);
-        self.set("".into(), "".into());
+        self.reseal();
+    }
+
+    pub fn set_version(&mut self, version: String) {
+        assert_eq!(version.len(), 8);
+        self.version = version;
+        self.reseal();
+    }
+
+    fn stored_version(raw_dbid: &str) -> Option<String> {
+        let mut db = AppDB::new();
+        db.set_db_id(raw_dbid.to_owned());
+        db.store.read(&db.db_path()).ok()
+            .filter(|rdb| AppDB::is_well_formed(rdb))
+            .map(|rdb| rdb[..8].to_owned())
+    }
+
+    // Returns the version to unlock with and its key. A local copy records the version it was sealed with;
+    // anything else starts out current, the only profile there is so far.
+    pub fn discover_key(raw_dbid: &str, password: &str, second_password: &str) -> (String, Zeroizing<[u8; 32]>) {
+        let version = AppDB::stored_version(raw_dbid).unwrap_or_else(|| CURRENT_VERSION.to_owned());
+        let key = AppDB::derive_key(password, second_password, &version);
+        (version, key)
     }
 
     fn db_path(&self) -> PathBuf {
@@ -81,31 +202,595 @@ impl AppDB {
         PathBuf::from(format!(".digisafe_{}.db", self.db_id))
     }
 
+    fn db_path_journal(&self) -> PathBuf {
+        PathBuf::from(format!(".digisafe_{}.journal", self.db_id))
+    }
+
     fn db_path_archive(&self) -> PathBuf {
         let archive_root = PathBuf::from("archive").join(&self.db_id);
         let archive_file = PathBuf::from(format!("digisafe_{}.db", self.revision));
         archive_root.join(archive_file)
     }
 
+    pub fn snapshot_dir(&self) -> PathBuf {
+        PathBuf::from("snapshots").join(&self.db_id)
+    }
+
+    fn db_paths_conflict(&self) -> Vec<PathBuf> {
+        let prefix = format!("digisafe_{}.sync-conflict-", self.db_id);
+        let mut conflicts: Vec<PathBuf> = self.store.list(Path::new(".")).into_iter()
+            .filter(|path| path.file_name()
+                .and_then(|file_name| file_name.to_str())
+                .is_some_and(|file_name| file_name.starts_with(&prefix) && file_name.ends_with(".db")))
+            .collect();
+        conflicts.sort();
+        conflicts
+    }
+
+    pub fn conflict_count(&self) -> usize {
+        self.db_paths_conflict().len()
+    }
+
+    pub fn merge_conflicts(&mut self) -> String {
+        let conflicts = self.db_paths_conflict();
+        for conflict in &conflicts {
+            let rdb = self.store.read(conflict).unwrap_or_default();
+            if !self.is_compatible(&rdb) {
+                self.lock();
+                return "merge failure E1".into();
+            }
+            if self.unlock_enc(&rdb) != "unlocked" {
+                self.lock();
+                return "merge failure E2".into();
+            }
+        }
+        // Entries from the main database win over conflicting copies.
+        self.unlock();
+        self.seal();
+        self.lock();
+        let msg = self.save();
+        if !msg.starts_with("saved") {
+            return msg;
+        }
+        for conflict in &conflicts {
+            if self.store.remove(conflict).is_err() {
+                return "merge failure E3".into();
+            }
+        }
+        format!("merged {} sync conflicts", conflicts.len())
+    }
+
+    pub fn newer_remote_revision(remote_id: &str, revision: &str) -> Result<Option<String>, String> {
+        let rdb = match remote::configured()? {
+            Some(remote) => remote.download(remote_id)?,
+            None => return Ok(None),
+        };
+        if AppDB::is_well_formed(&rdb) && &rdb[16..24] > revision {
+            Ok(Some(rdb[16..24].to_owned()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn merge_remote(&mut self) -> String {
+        if !self.pulls() {
+            return "merge skipped (push-only)".into();
+        }
+        let rdb = match self.download_db() {
+            Ok(rdb) => rdb,
+            Err(_) => return "merge failure E4".into(),
+        };
+        if !self.is_compatible(&rdb) {
+            return "merge failure E1".into();
+        }
+        if !self.pushes() {
+            return self.mirror_remote(rdb);
+        }
+        // The newer remote revision wins over local entries.
+        self.unlock();
+        if self.unlock_enc(&rdb) != "unlocked" {
+            self.lock();
+            return "merge failure E2".into();
+        }
+        let revision = rdb[16..24].to_owned();
+        if revision > self.revision {
+            self.revision = revision.clone();
+        }
+        self.seal();
+        self.lock();
+        let msg = self.save();
+        if !msg.starts_with("saved") {
+            return msg;
+        }
+        format!("merged remote revision {revision}")
+    }
+
+    // A read replica takes the remote copy as is rather than merging into it, so nothing local can hide upstream changes.
+    fn mirror_remote(&mut self, rdb: String) -> String {
+        let mirrored = match self.open_scratch(&rdb) {
+            Some(mirrored) => mirrored,
+            None => return "merge failure E2".into(),
+        };
+        self.revision = rdb[16..24].to_owned();
+        self.db_enc = rdb;
+        self.created = mirrored.created;
+        self.entry_count = mirrored.db_map.len();
+        self.pending_changes = false;
+        if self.write_local().is_err() {
+            return "merge failure E3".into();
+        }
+        format!("mirrored remote revision {}", self.revision)
+    }
+
+    pub fn snapshot(&self) -> String {
+        if self.db_enc.is_empty() {
+            return "snapshot skipped".into();
+        }
+        let now = SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |since_epoch| since_epoch.as_secs());
+        let snapshot_path = self.snapshot_dir().join(format!("digisafe_{now}.db"));
+        if self.store.write(&snapshot_path, &self.db_enc).is_err() {
+            return "snapshot failure E1".into();
+        }
+        if self.prune_snapshots().is_err() {
+            return "snapshot failure E2".into();
+        }
+        "snapshot taken".into()
+    }
+
+    fn prune_snapshots(&self) -> std::io::Result<()> {
+        let mut snapshots: Vec<(u64, PathBuf)> = Vec::new();
+        for path in self.store.list(&self.snapshot_dir()) {
+            let taken = path.file_name()
+                .and_then(|file_name| file_name.to_str())
+                .and_then(|file_name| file_name.strip_prefix("digisafe_"))
+                .and_then(|file_name| file_name.strip_suffix(".db"))
+                .and_then(|taken| taken.parse::<u64>().ok());
+            if let Some(taken) = taken {
+                snapshots.push((taken, path));
+            }
+        }
+        snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.0));
+        // Keep the newest snapshot of each of the last 24 hours, 7 days and 4 weeks.
+        let mut keep = vec![false; snapshots.len()];
+        for (bucket_secs, bucket_count) in [(3600, 24), (86400, 7), (604800, 4)] {
+            let mut buckets = Vec::new();
+            for (idx, (taken, _)) in snapshots.iter().enumerate() {
+                let bucket = taken / bucket_secs;
+                if buckets.len() < bucket_count && !buckets.contains(&bucket) {
+                    buckets.push(bucket);
+                    keep[idx] = true;
+                }
+            }
+        }
+        for ((_, path), keep) in snapshots.iter().zip(keep) {
+            if !keep {
+                self.store.remove(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn restore_snapshot(&mut self, path: &Path) -> String {
+        let rdb = self.store.read(path).unwrap_or_default();
+        self.restore_revision(&rdb)
+    }
+
+    pub fn restore_revision(&mut self, rdb: &str) -> String {
+        if !self.is_compatible(rdb) {
+            return "restore failure E1".into();
+        }
+        // Replace the entries but keep the current revision, so saving the restore supersedes newer copies.
+        self.lock();
+        if self.unlock_enc(rdb) != "unlocked" {
+            self.lock();
+            return "restore failure E2".into();
+        }
+        self.seal();
+        self.lock();
+        self.pending_changes = true;
+        format!("restored revision {}, save to keep it", &rdb[16..24])
+    }
+
+    // Snapshots and archived revisions of this database on this machine, newest first.
+    pub fn revision_files(&self) -> Vec<(&'static str, PathBuf, Option<SystemTime>)> {
+        let mut revisions = Vec::new();
+        let archive_dir = PathBuf::from("archive").join(&self.db_id);
+        for (source, dir) in [("snapshot", self.snapshot_dir()), ("archive", archive_dir)] {
+            for path in self.store.list(&dir) {
+                let modified = self.store.modified(&path);
+                revisions.push((source, path, modified));
+            }
+        }
+        revisions.sort_by_key(|revision| std::cmp::Reverse(revision.2));
+        revisions
+    }
+
+    // Reads a stored revision from a file, or the current remote copy when no path is given.
+    pub fn read_revision(&self, path: Option<&Path>) -> Result<String, String> {
+        match path {
+            Some(path) => self.store.read(path).map_err(|err| err.to_string()),
+            None if self.store.has_remote() => self.download_db(),
+            None => Err("no remote configured".into()),
+        }
+    }
+
+    pub fn inspect_revision(&self, rdb: &str) -> Result<(String, usize), String> {
+        if !self.is_compatible(rdb) {
+            return Err("not a revision of this database".into());
+        }
+        match self.open_scratch(rdb) {
+            Some(scratch) => Ok((rdb[16..24].to_owned(), scratch.db_map.len())),
+            None => Err("revision does not decrypt".into()),
+        }
+    }
+
+    // Pairs each key in either copy with how the stored revision differs from the live entries.
+    pub fn compare_revision(&mut self, rdb: &str) -> Result<Vec<(String, &'static str)>, String> {
+        if !self.is_compatible(rdb) {
+            return Err("not a revision of this database".into());
+        }
+        let scratch = self.open_scratch(rdb).ok_or("revision does not decrypt")?;
+        self.unlock();
+        let mut keys: Vec<&String> = scratch.db_map.keys().chain(self.db_map.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        let compared = keys.into_iter().map(|key| {
+            let status = match (scratch.db_map.get(key), self.db_map.get(key)) {
+                (Some(_), None) => "deleted since",
+                (None, _) => "added since",
+                (Some(stored), Some(live)) if stored == live => "unchanged",
+                (Some(_), Some(_)) => "changed since",
+            };
+            (key.to_owned(), status)
+        }).collect();
+        self.lock();
+        Ok(compared)
+    }
+
+    pub fn restore_entries(&mut self, rdb: &str, keys: &[String]) -> String {
+        if !self.is_compatible(rdb) {
+            return "restore failure E1".into();
+        }
+        let scratch = match self.open_scratch(rdb) {
+            Some(scratch) => scratch,
+            None => return "restore failure E2".into(),
+        };
+        self.unlock();
+        let mut restored = 0;
+        for key in keys {
+            if let Some(aval) = scratch.db_map.get(key) {
+                self.db_map.insert(key.to_owned(), aval.to_owned());
+                restored += 1;
+            }
+        }
+        self.seal();
+        self.lock();
+        if restored > 0 {
+            self.pending_changes = true;
+        }
+        format!("restored {restored} entries, save to keep them")
+    }
+
+    // Decrypts into a scratch instance so the live database is never touched.
+    fn open_scratch(&self, rdb: &str) -> Option<AppDB> {
+        let mut scratch = AppDB::new();
+        scratch.db_id = self.db_id.to_string();
+        scratch.version = self.version.to_string();
+        scratch.password = self.password;
+        if scratch.unlock_enc(rdb) == "unlocked" {
+            Some(scratch)
+        } else {
+            None
+        }
+    }
+
+    pub fn write_recovery(&self, dir: &Path) -> String {
+        if self.db_enc.is_empty() {
+            return "recovery drive skipped".into();
+        }
+        let recovery_dir = dir.join("digisafe-recovery");
+        if std::fs::create_dir_all(&recovery_dir).is_err() {
+            return "recovery drive failure E1".into();
+        }
+        if AppDB::write_synced(&recovery_dir.join(self.db_path()), &self.db_enc).is_err() {
+            return "recovery drive failure E2".into();
+        }
+        let copied = std::env::current_exe().and_then(|exe| std::fs::copy(exe, recovery_dir.join("digisafe")));
+        if copied.is_err() {
+            return "recovery drive failure E3".into();
+        }
+        let readme = format!(
+            "DigiSafe recovery copy of database {db_id}, revision {revision}.\n\n\
+            1. Copy this folder to a Linux machine with GTK 4 installed.\n\
+            2. Run ./digisafe from inside the folder, so it finds {db_file}.\n\
+            3. Unlock with database id {db_id} and the database password.\n\n\
+            The database is encrypted and useless without the password. No remote credentials are stored here.\n",
+            db_id = self.db_id,
+            revision = self.revision,
+            db_file = self.db_path().display(),
+        );
+        if AppDB::write_synced(&recovery_dir.join("README.txt"), &readme).is_err() {
+            return "recovery drive failure E4".into();
+        }
+        format!("recovery drive written to {}", recovery_dir.display())
+    }
+
+    pub fn email_backup(&self) -> String {
+        if !crate::mail::is_configured() {
+            return "email backup skipped (no smtp config)".into();
+        }
+        if self.db_enc.is_empty() {
+            return "email backup skipped".into();
+        }
+        match crate::mail::send_backup(&self.db_id, &self.revision, &self.db_enc) {
+            Ok(1) => "email backup sent".into(),
+            Ok(parts) => format!("email backup sent in {parts} parts"),
+            Err(err) => format!("email backup failure: {err}"),
+        }
+    }
+
+    pub fn test_restore(&self) -> String {
+        if !self.store.has_remote() {
+            return "restore test skipped (local only)".into();
+        }
+        let rdb = match self.download_db() {
+            Ok(rdb) => rdb,
+            Err(_) => return "restore test failure E1".into(),
+        };
+        if !self.is_compatible(&rdb) {
+            return "restore test failure E2".into();
+        }
+        let restored = match self.open_scratch(&rdb) {
+            Some(restored) => restored,
+            None => return "restore test failure E3".into(),
+        };
+        format!("restore test passed: revision {}, {} entries", &rdb[16..24], restored.db_map.len())
+    }
+
+    fn is_compatible(&self, rdb: &str) -> bool {
+        AppDB::is_well_formed(rdb) && rdb[..8] == self.version && rdb[8..16] == self.db_id
+    }
+
+    fn is_well_formed(rdb: &str) -> bool {
+        rdb.len() >= 40 && rdb.is_ascii() && rdb[16..24].bytes().all(|b| b.is_ascii_digit())
+    }
+
+    pub fn import(&mut self, entries: Vec<(String, SecretString)>) -> String {
+        self.unlock();
+        let mut imported = 0;
+        let mut skipped = 0;
+        for (akey, aval) in entries {
+            if akey.is_empty() || self.db_map.contains_key(&akey) {
+                skipped += 1;
+            } else {
+                self.db_map.insert(akey, aval.to_string());
+                imported += 1;
+            }
+        }
+        if imported > 0 {
+            self.pending_changes = true;
+        }
+        self.seal();
+        self.lock();
+        format!("imported {imported} entries ({skipped} skipped)")
+    }
+
+    pub fn export_json(&mut self, path: &Path, include_values: bool) -> String {
+        use std::io::Write;
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        self.unlock();
+        let export_str = if include_values {
+            SecretString::new(serde_json::to_string_pretty(&self.db_map).unwrap())
+        } else {
+            let keys: Vec<&String> = self.db_map.keys().collect();
+            SecretString::new(serde_json::to_string_pretty(&keys).unwrap())
+        };
+        self.lock();
+        let export_file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path);
+        // The mode only applies to a new file, so an existing one is restricted before anything is written to it.
+        let exported = export_file.and_then(|mut export_file| {
+            export_file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+            export_file.write_all(export_str.as_bytes())
+        });
+        match exported {
+            Ok(()) => "exported".into(),
+            Err(_) => "export failure E1".into(),
+        }
+    }
+
+    pub fn remote_id(&self) -> String {
+        use sha2::Digest;
+        // Keyed on the password hash so the storage provider cannot link objects to db ids.
+        let remote_id_arg = Zeroizing::new(base64::encode(self.password) + "remote" + &self.db_id);
+        let remote_id_pre: [u8; 32] = Sha256::digest(remote_id_arg.as_bytes()).into();
+        hex::encode(&Sha256::digest(remote_id_pre)[..16])
+    }
+
+    pub fn version(&self) -> String {
+        self.version.to_string()
+    }
+
+    pub fn db_id(&self) -> String {
+        self.db_id.to_string()
+    }
+
+    pub fn revision(&self) -> String {
+        self.revision.to_string()
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.entry_count
+    }
+
+    pub fn approximate_size(&self) -> usize {
+        self.db_enc.len()
+    }
+
+    // Entries are flat keys without tags, so there are no per-tag counts to report alongside these.
+    pub fn created_ts(&self) -> Option<SystemTime> {
+        self.created.map(|created| std::time::UNIX_EPOCH + std::time::Duration::from_secs(created))
+    }
+
+    pub fn modified_ts(&self) -> Option<SystemTime> {
+        self.store.modified(&self.db_path())
+    }
+
+    pub fn last_save(&self) -> Option<SystemTime> {
+        self.last_save
+    }
+
+    pub fn last_sync(&self) -> Option<SystemTime> {
+        self.last_sync
+    }
+
+    pub fn pending_changes(&self) -> bool {
+        self.pending_changes
+    }
+
     pub fn load(&mut self) -> String {
-        if self.db_path().exists() {
-            let rdb = std::fs::read_to_string(self.db_path());
+        // A push-only database treats the local copy as the source of truth.
+        let downloaded = if self.pulls() { self.download_db() } else { Ok("".to_owned()) };
+        let mut remote_revision = None;
+        let msg = match downloaded {
+            Ok(rdb_bak) => {
+                if self.store.has_remote() && self.pulls() {
+                    self.last_sync = Some(SystemTime::now());
+                }
+                if self.is_compatible(&rdb_bak) {
+                    // A provider serving an older intact copy than this device has seen may be rolling it back.
+                    let last_seen = settings::get(&self.last_revision_key()).unwrap_or_default();
+                    if rdb_bak[16..24] < *last_seen {
+                        self.remote_rollback = Some((rdb_bak[16..24].to_owned(), last_seen));
+                    }
+                    remote_revision = Some(rdb_bak[16..24].to_owned());
+                }
+                self.load_from(rdb_bak)
+            }
+            Err(_) => {
+                if self.store.exists(&self.db_path()) {
+                    let msg = self.load_from("".to_owned());
+                    if msg == "unlocked" {
+                        "unlocked, remote unavailable".into()
+                    } else {
+                        msg
+                    }
+                } else {
+                    "load failure E2".into()
+                }
+            }
+        };
+        if msg.starts_with("unlocked") {
+            if let Some(remote_revision) = remote_revision {
+                self.record_revision(&remote_revision);
+                // A read replica keeps the remote copy locally, so it still opens offline.
+                if !self.pushes() && self.write_local().is_err() {
+                    return format!("{msg}, local copy not updated");
+                }
+            }
+            if let Some(recovery) = self.recover_journal() {
+                return format!("{msg}, {recovery}");
+            }
+        }
+        msg
+    }
+
+    fn recover_journal(&mut self) -> Option<String> {
+        let journal_revision = self.store.read(&self.db_path_journal()).ok()?;
+        let local_revision = self.store.read(&self.db_path()).ok()
+            .filter(|rdb| self.is_compatible(rdb))
+            .map(|rdb| rdb[16..24].to_owned())
+            .unwrap_or_default();
+        if local_revision != journal_revision {
+            // The save stopped before the rename, so the local file is still the previous complete save.
+            let _ = self.store.remove(&self.db_path_hidden());
+            let _ = self.store.remove(&self.db_path_journal());
+            return Some("rolled back interrupted save".into());
+        }
+        if self.revision != local_revision {
+            let _ = self.store.remove(&self.db_path_journal());
+            return Some("interrupted save superseded by remote".into());
+        }
+        // The local file is complete, so roll forward through archiving and uploading.
+        if !self.store.exists(&self.db_path_archive()) {
+            let archived = self.store.read(&self.db_path())
+                .and_then(|rdb| self.store.write(&self.db_path_archive(), &rdb));
+            if archived.is_err() {
+                return Some("interrupted save not archived".into());
+            }
+        }
+        match self.backup_db(&|_, _| {}) {
+            Ok(_) => {
+                let _ = self.store.remove(&self.db_path_journal());
+                Some("finished interrupted save".into())
+            }
+            Err(_) => Some("interrupted save not uploaded".into()),
+        }
+    }
+
+    fn last_revision_key(&self) -> String {
+        format!("last_revision_{}", self.db_id)
+    }
+
+    // Only revisions seen on the remote count, since a local save that never uploaded is no rollback.
+    fn record_revision(&self, revision: &str) {
+        let last_seen = settings::get(&self.last_revision_key()).unwrap_or_default();
+        if *revision > *last_seen {
+            let _ = settings::set(&self.last_revision_key(), revision);
+        }
+    }
+
+    fn legacy_removed_key(&self) -> String {
+        format!("legacy_removed_{}", self.db_id)
+    }
+
+    fn sync_direction_key(&self) -> String {
+        format!("sync_direction_{}", self.db_id)
+    }
+
+    // One of "both", "push" (this machine is the source of truth) or "pull" (read replica).
+    pub fn sync_direction(&self) -> String {
+        settings::get(&self.sync_direction_key()).unwrap_or_else(|| "both".to_owned())
+    }
+
+    pub fn set_sync_direction(&self, direction: &str) -> Result<(), String> {
+        let direction = if direction == "both" { "" } else { direction };
+        settings::set(&self.sync_direction_key(), direction)
+    }
+
+    fn pushes(&self) -> bool {
+        self.sync_direction() != "pull"
+    }
+
+    fn pulls(&self) -> bool {
+        self.sync_direction() != "push"
+    }
+
+    pub fn remote_rollback(&self) -> Option<(String, String)> {
+        self.remote_rollback.clone()
+    }
+
+    fn load_from(&mut self, rdb_bak: String) -> String {
+        // A truncated, foreign or newer-format remote copy is ignored.
+        let rdb_bak = if self.is_compatible(&rdb_bak) { rdb_bak } else { "".to_owned() };
+        if self.store.exists(&self.db_path()) {
+            let rdb = self.store.read(&self.db_path());
             if rdb.is_ok() {
                 let rdb = rdb.unwrap();
-                let version = rdb[..8].to_owned();
-                let db_id = rdb[8..16].to_owned();
+                if !self.is_compatible(&rdb) {
+                    return "load failure E3".into();
+                }
                 let revision = rdb[16..24].to_owned();
                 let db_enc = rdb.to_owned();
-                let rdb_bak = self.download_db();
-                assert_eq!(self.db_id, db_id);
-                assert_eq!(self.version, version);
                 if rdb_bak != "" {
-                    let version_bak = rdb_bak[..8].to_owned();
-                    let db_id_bak = rdb_bak[8..16].to_owned();
                     let revision_bak = rdb_bak[16..24].to_owned();
                     let db_enc_bak = rdb_bak.to_owned();
-                    assert_eq!(self.db_id, db_id_bak);
-                    if version_bak == version && revision_bak > revision {
+                    // A read replica never saves, so any difference is an upstream change.
+                    if revision_bak > revision || (!self.pushes() && db_enc_bak != db_enc) {
                         self.db_enc = db_enc_bak;
                         self.revision = revision_bak;
                     } else {
@@ -120,43 +805,54 @@ impl AppDB {
             } else {
                 "load failure E1".into()
             }
+        } else if rdb_bak != "" {
+            self.revision = rdb_bak[16..24].to_owned();
+            self.db_enc = rdb_bak;
+            self.unlock()
         } else {
-            let rdb_bak = self.download_db();
-            if rdb_bak != "" {
-                let version_bak = rdb_bak[..8].to_owned();
-                let db_id_bak = rdb_bak[8..16].to_owned();
-                let revision_bak = rdb_bak[16..24].to_owned();
-                let db_enc_bak = rdb_bak.to_owned();
-                assert_eq!(self.db_id, db_id_bak);
-                if version_bak == self.version {
-                    self.db_enc = db_enc_bak;
-                    self.revision = revision_bak;
-                    self.unlock()
-                } else {
-                    "unlocked".into()
-                }
-            } else {
-                "unlocked".into()
-            }
+            "unlocked".into()
         }
     }
 
     pub fn save(&mut self) -> String {
+        self.save_with_progress(|_, _| {})
+    }
+
+    pub fn save_with_progress<F: Fn(f64, &str)>(&mut self, progress: F) -> String {
+        if !self.pushes() {
+            return "save skipped (pull-only)".into();
+        }
         self.revision = format!("{:0>8}", self.revision.parse::<u16>().unwrap() + 1);
-        self.set("".into(), "".into());
-        let wr1 = std::fs::write(self.db_path_hidden(), &self.db_enc);
+        if self.created.is_none() {
+            // Databases sealed before the creation time was stored fall back to their first archived revision.
+            let first_archive = PathBuf::from("archive").join(&self.db_id).join("digisafe_00000001.db");
+            let created = self.store.modified(&first_archive)
+                .or_else(|| (self.revision == "00000001").then(SystemTime::now));
+            self.created = created.and_then(|created| created.duration_since(std::time::UNIX_EPOCH).ok()).map(|created| created.as_secs());
+        }
+        self.reseal();
+        progress(0.1, "saving: writing local copy");
+        // The journal names the revision in flight until the remote copy is verified.
+        let wr1 = self.store.write(&self.db_path_journal(), &self.revision)
+            .and_then(|_| self.store.write(&self.db_path_hidden(), &self.db_enc));
         if wr1.is_ok() {
-            let wr2 = std::fs::rename(self.db_path_hidden(), &self.db_path());
+            let wr2 = self.store.rename(&self.db_path_hidden(), &self.db_path());
             if wr2.is_ok() {
-                let wr3 = std::fs::create_dir_all(self.db_path_archive().parent().unwrap());
-                if wr3.is_ok() {
-                    let wr4 = std::fs::copy(self.db_path(), self.db_path_archive());
+                progress(0.3, "saving: archiving");
+                self.last_save = Some(SystemTime::now());
+                self.pending_changes = false;
+                let wr3 = self.store.read(&self.db_path());
+                if let Ok(rdb) = wr3 {
+                    let wr4 = self.store.write(&self.db_path_archive(), &rdb);
                     if wr4.is_ok() {
-                        let res = self.backup_db();
-                        if res.is_ok() {
-                            "saved".into()
-                        } else {
-                            "save failure E5".into()
+                        let backed_up = self.backup_db(&progress);
+                        if backed_up.is_ok() {
+                            let _ = self.store.remove(&self.db_path_journal());
+                        }
+                        match backed_up {
+                            Ok(true) => "saved, verified".into(),
+                            Ok(false) => "saved (local only)".into(),
+                            Err(_) => "save failure E5".into(),
                         }
                     } else {
                         "save failure E4".into()
@@ -172,25 +868,54 @@ impl AppDB {
         }
     }
 
+    fn write_local(&self) -> std::io::Result<()> {
+        self.store.write(&self.db_path_hidden(), &self.db_enc)?;
+        self.store.rename(&self.db_path_hidden(), &self.db_path())
+    }
+
+    fn write_synced(path: &Path, contents: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let mut file = std::fs::File::create(path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()
+    }
+
     fn unlock(&mut self) -> String {
+        let db_enc = self.db_enc.clone();
+        self.unlock_enc(&db_enc)
+    }
+
+    fn unlock_enc(&mut self, db_enc: &str) -> String {
         use sha2::Digest;
-        if self.db_enc == "" {
+        if db_enc == "" {
             "unlocked".into()
+        } else if !AppDB::is_well_formed(db_enc) {
+            "unlock failure E3".into()
         } else {
-            let nonce: [u8; 12] = base64::decode(&self.db_enc[24..40]).unwrap().try_into().unwrap();
-            let db_map_enc = &self.db_enc[40..];
+            let nonce: [u8; 12] = match base64::decode(&db_enc[24..40]).ok().and_then(|nonce| nonce.try_into().ok()) {
+                Some(nonce) => nonce,
+                None => return "unlock failure E3".into(),
+            };
+            let db_map_enc = &db_enc[40..];
             let db_map_str = AppDB::decrypt(db_map_enc.into(), self.password, &nonce);
             if db_map_str.is_some() {
                 let db_map_str = db_map_str.unwrap();
-                let pre_prefix = &self.db_enc[..24];
-                let hmac_arg = base64::encode(self.password) + &pre_prefix + &db_map_str;
-                let hmac_pre: [u8; 32] = Sha256::digest(hmac_arg).try_into().unwrap();
-                let hmac: [u8; 32] = Sha256::digest(hmac_pre).try_into().unwrap();
+                let pre_prefix = &db_enc[..24];
+                let hmac_arg = Zeroizing::new(base64::encode(self.password) + &pre_prefix + &db_map_str);
+                let hmac_pre: Zeroizing<[u8; 32]> = Zeroizing::new(Sha256::digest(hmac_arg.as_bytes()).into());
+                let hmac: [u8; 32] = Sha256::digest(hmac_pre.as_slice()).into();
                 let nonce_check: [u8; 12] = hmac[..12].try_into().unwrap();
-                assert_eq!(nonce, nonce_check);
-                let rdb: Result<HashMap<String, String>, _> = serde_json::from_str(&db_map_str);
+                if !bool::from(nonce.ct_eq(&nonce_check)) {
+                    return "unlock failure E4".into();
+                }
+                let rdb: Result<BTreeMap<String, String>, _> = serde_json::from_str(&db_map_str);
                 if rdb.is_ok() {
-                    self.db_map.extend(rdb.unwrap().into_iter());
+                    let mut rdb = rdb.unwrap();
+                    // Merged copies keep the earliest creation time.
+                    if let Some(created) = rdb.remove(CREATED_KEY).and_then(|created| created.parse::<u64>().ok()) {
+                        self.created = Some(self.created.map_or(created, |own| own.min(created)));
+                    }
+                    self.db_map.extend(rdb.into_iter());
                     "unlocked".into()
                 } else {
                     "unlock failure E2".into()
@@ -201,43 +926,77 @@ impl AppDB {
         }
     }
 
+    fn reseal(&mut self) {
+        self.unlock();
+        self.seal();
+        self.lock();
+    }
+
     fn lock(&mut self) {
+        self.entry_count = self.db_map.len();
+        for aval in self.db_map.values_mut() {
+            aval.zeroize();
+        }
         self.db_map.clear();
     }
 
-    fn hash_password(password: String) -> [u8; 32] {
+    // An empty second password means a single-person unlock.
+    pub fn derive_key(password: &str, second_password: &str, version: &str) -> Zeroizing<[u8; 32]> {
+        let key = AppDB::hash_password(password, version);
+        if second_password.is_empty() {
+            return key;
+        }
+        AppDB::combine_password_hashes(&key, &AppDB::hash_password(second_password, version))
+    }
+
+    fn hash_password(password: &str, version: &str) -> Zeroizing<[u8; 32]> {
+        // An unknown version cannot be unlocked anyway, since is_compatible rejects it.
+        let profile = KDF_PROFILES.iter().find(|profile| profile.version == version).unwrap_or(&KDF_PROFILES[0]);
         let salt = b"digisafe";
         let config = argon2::Config {
             variant: argon2::Variant::Argon2id,
             version: argon2::Version::Version13,
-            mem_cost: 1048576,
-            time_cost: 2,
-            lanes: 4,
+            mem_cost: profile.mem_cost,
+            time_cost: profile.time_cost,
+            lanes: profile.lanes,
             thread_mode: argon2::ThreadMode::Parallel,
             secret: &[],
             ad: &[],
             hash_length: 32
         };
+        let kdf_guard = KDF_GUARD.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
         let vhash = argon2::hash_raw(password.as_bytes(), salt, &config).unwrap();
+        drop(kdf_guard);
         let hash: [u8; 32] = vhash.try_into().unwrap();
-        hash
+        Zeroizing::new(hash)
+    }
+
+    fn combine_password_hashes(first: &[u8; 32], second: &[u8; 32]) -> Zeroizing<[u8; 32]> {
+        use sha2::Digest;
+        // Unlike XOR, hashing the pair cannot collapse to a zero key when both passwords match; order matters.
+        let mut hasher = Sha256::new();
+        hasher.update(b"digisafe split knowledge");
+        hasher.update(first);
+        hasher.update(second);
+        Zeroizing::new(hasher.finalize().into())
     }
 
-    fn encrypt(raw_text: String, key: [u8; 32], nonce: &[u8; 12]) -> String {
+    fn encrypt(raw_text: &str, key: [u8; 32], nonce: &[u8; 12]) -> String {
         let cipher = ChaCha20Poly1305::new(&key.into());
         let cipher_text = cipher.encrypt(nonce.into(), raw_text.as_ref()).unwrap();
         base64::encode(cipher_text)
     }
 
-    fn decrypt(enc_text: String, key: [u8; 32], nonce: &[u8; 12]) -> Option<String> {
+    fn decrypt(enc_text: String, key: [u8; 32], nonce: &[u8; 12]) -> Option<SecretString> {
         let cipher = ChaCha20Poly1305::new(&key.into());
-        let blob = base64::decode(enc_text).unwrap();
+        let blob = base64::decode(enc_text).ok()?;
         let vplain_text = cipher.decrypt(nonce.into(), blob.as_ref());
         if vplain_text.is_ok() {
             let plain_text = String::from_utf8(vplain_text.unwrap());
             if plain_text.is_ok() {
-                Some(plain_text.unwrap())
+                Some(SecretString::new(plain_text.unwrap()))
             } else {
+                plain_text.unwrap_err().into_bytes().zeroize();
                 None
             }
         } else {
@@ -245,63 +1004,251 @@ impl AppDB {
         }
     }
 
-    fn backup_db(&self) -> Result<String, reqwest::Error> {
-        use sha1::Digest;
-        let api_config: HashMap<String, String> = serde_json::from_str(&std::fs::read_to_string("/secrets/backblaze.json").unwrap()).unwrap();
-        let api_key = base64::encode(format!("{}:{}", api_config["key_id"], api_config["app_key"]));
-        let auth_url = "https://api.backblazeb2.com/b2api/v2/b2_authorize_account";
-        let b2 = reqwest::blocking::Client::new();
-        let auth_req = b2.get(auth_url).header("Authorization", format!("Basic {api_key}")).build().unwrap();
-        let auth_resp = b2.execute(auth_req).unwrap().text().unwrap();
-        let auth: HashMap<String, serde_json::Value> = serde_json::from_str(&auth_resp).unwrap();
-        let auth_token = auth["authorizationToken"].clone().as_str().unwrap().to_string();
-        let bucket_id = auth["allowed"]["bucketId"].clone().as_str().unwrap().to_string();
-        let api_url = auth["apiUrl"].clone().as_str().unwrap().to_string();
-        let upload_url_req = b2.post(format!("{api_url}/b2api/v2/b2_get_upload_url"))
-            .body(format!("{{\"bucketId\":\"{bucket_id}\"}}"))
-            .header("Authorization", format!("{auth_token}"))
-            .build().unwrap();
-        let upload_url_resp = b2.execute(upload_url_req).unwrap().text().unwrap();
-        let upload_url_resp_map: HashMap<String, serde_json::Value> = serde_json::from_str(&upload_url_resp).unwrap();
-        let upload_url = upload_url_resp_map["uploadUrl"].clone().as_str().unwrap().to_string();
-        let upload_token = upload_url_resp_map["authorizationToken"].clone().as_str().unwrap().to_string();
-        let mut sha1_hasher: Sha1 = Sha1::new();
-        sha1_hasher.update(self.db_enc.as_bytes());
-        let sha1_hash = hex::encode(sha1_hasher.finalize());
-        let file_path = format!("{}/{}", self.db_id, "digisafe.db");
-        let upload_req = b2.post(upload_url).body(self.db_enc.to_string())
-            .header("Authorization", format!("{upload_token}"))
-            .header("X-Bz-File-Name", file_path)
-            .header("Content-Type", "text/plain")
-            .header("X-Bz-Content-Sha1", sha1_hash)
-            .header("X-Bz-Info-Author", "DigiSafe")
-            .header("X-Bz-Server-Side-Encryption", "AES256")
-            .build().unwrap();
-        let upload_resp = b2.execute(upload_req).unwrap().text();
-        upload_resp
-    }
-
-    fn download_db(&self) -> String {
-        let api_config: HashMap<String, String> = serde_json::from_str(&std::fs::read_to_string("/secrets/backblaze.json").unwrap()).unwrap();
-        let api_key = base64::encode(format!("{}:{}", api_config["key_id"], api_config["app_key"]));
-        let auth_url = "https://api.backblazeb2.com/b2api/v2/b2_authorize_account";
-        let b2 = reqwest::blocking::Client::new();
-        let auth_req = b2.get(auth_url).header("Authorization", format!("Basic {api_key}")).build().unwrap();
-        let auth_resp = b2.execute(auth_req).unwrap().text().unwrap();
-        let auth: HashMap<String, serde_json::Value> = serde_json::from_str(&auth_resp).unwrap();
-        let auth_token = auth["authorizationToken"].clone().as_str().unwrap().to_string();
-        let download_url = auth["downloadUrl"].clone().as_str().unwrap().to_string();
-        let db_id = self.db_id.to_string();
-        let download_req = b2.get(format!("{download_url}/file/digisafe/{db_id}/digisafe.db"))
-            .header("Authorization", format!("{auth_token}"))
-            .build().unwrap();
-        let download_resp = b2.execute(download_req).unwrap();
-        if download_resp.status() == 200 {
-            download_resp.text().unwrap()
+    fn backup_db<F: Fn(f64, &str)>(&mut self, progress: &F) -> Result<bool, String> {
+        let remote = match self.store.remote()? {
+            Some(remote) if self.pushes() => remote,
+            _ => return Ok(false),
+        };
+        progress(0.5, "saving: uploading");
+        remote.upload(&self.remote_id(), &self.db_enc)?;
+        progress(0.8, "saving: verifying remote copy");
+        if remote.verify(&self.remote_id(), &self.db_enc) {
+            self.last_sync = Some(SystemTime::now());
+            self.record_revision(&self.revision);
+            // The copy stored under the db id lets the provider link objects to it, so drop it once the opaque copy is verified.
+            if settings::get(&self.legacy_removed_key()).is_none() && remote.delete(&self.db_id).is_ok() {
+                let _ = settings::set(&self.legacy_removed_key(), "true");
+            }
+            Ok(true)
         } else {
-            "".to_owned()
+            Err("remote verification failed".into())
+        }
+    }
+
+    fn download_db(&self) -> Result<String, String> {
+        match self.store.remote()? {
+            Some(remote) => {
+                let rdb = remote.download(&self.remote_id())?;
+                if rdb.is_empty() && settings::get(&self.legacy_removed_key()).is_none() {
+                    // Databases saved before remote ids were opaque are stored under the db id.
+                    remote.download(&self.db_id)
+                } else {
+                    Ok(rdb)
+                }
+            }
+            None => Ok("".to_owned()),
         }
     }
 
 }
 
+impl Drop for AppDB {
+
+    fn drop(&mut self) {
+        self.lock();
+        self.password.zeroize();
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::store::MemoryFiles;
+
+    // A fixed key stands in for the 1 GiB Argon2 derivation.
+    fn test_db_on(files: &MemoryFiles) -> AppDB {
+        let mut db = AppDB::with_store(files.clone());
+        db.set_password_hash(&[7; 32]);
+        db
+    }
+
+    fn test_db() -> AppDB {
+        test_db_on(&MemoryFiles::default())
+    }
+
+    fn saved_db(files: &MemoryFiles) -> AppDB {
+        let mut db = test_db_on(files);
+        db.set("mail".into(), secret("hunter2"));
+        assert_eq!(db.save(), "saved (local only)");
+        db
+    }
+
+    fn secret(aval: &str) -> SecretString {
+        SecretString::new(aval.to_owned())
+    }
+
+    #[test]
+    fn set_leaves_no_plaintext() {
+        let mut db = test_db();
+        db.set("mail".into(), secret("hunter2"));
+        assert!(db.db_map.is_empty());
+        assert!(!db.db_enc.contains("hunter2"));
+        assert!(!db.db_enc.contains("mail"));
+        assert_eq!(db.entry_count(), 1);
+    }
+
+    #[test]
+    fn get_and_keys_relock() {
+        let mut db = test_db();
+        db.set("mail".into(), secret("hunter2"));
+        assert_eq!(db.get("mail").as_deref().map(|aval| aval.as_str()), Some("hunter2"));
+        assert!(db.db_map.is_empty());
+        assert_eq!(db.keys(), vec!["mail".to_owned()]);
+        assert!(db.db_map.is_empty());
+    }
+
+    #[test]
+    fn reseal_relocks() {
+        let mut db = test_db();
+        db.set("mail".into(), secret("hunter2"));
+        db.set_db_id("42".into());
+        assert!(db.db_map.is_empty());
+        assert!(!db.db_enc.contains("hunter2"));
+        assert_eq!(db.get("mail").as_deref().map(|aval| aval.as_str()), Some("hunter2"));
+    }
+
+    #[test]
+    fn rename_moves_the_stored_value() {
+        let mut db = test_db();
+        db.set("mail".into(), secret("hunter2"));
+        assert!(!db.rename("mail", "".into()));
+        assert!(!db.rename("missing", "other".into()));
+        assert!(db.rename("mail", "inbox".into()));
+        assert_eq!(db.keys(), vec!["inbox".to_owned()]);
+        assert_eq!(db.get("inbox").as_deref().map(|aval| aval.as_str()), Some("hunter2"));
+        assert!(db.db_map.is_empty());
+    }
+
+    #[test]
+    fn creation_time_is_sealed_apart_from_entries() {
+        let mut db = test_db();
+        db.created = Some(1_600_000_000);
+        db.set("mail".into(), secret("hunter2"));
+        let mut reopened = test_db();
+        assert_eq!(reopened.unlock_enc(&db.db_enc), "unlocked");
+        assert_eq!(reopened.created, Some(1_600_000_000));
+        assert_eq!(reopened.db_map.keys().collect::<Vec<_>>(), vec!["mail"]);
+        assert_eq!(db.keys(), vec!["mail".to_owned()]);
+        assert_eq!(db.entry_count(), 1);
+    }
+
+    #[test]
+    fn corrupt_blobs_fail_cleanly() {
+        let mut db = test_db();
+        db.set("mail".into(), secret("hunter2"));
+        let sealed = db.db_enc.clone();
+        let mut bad_nonce = sealed.clone();
+        bad_nonce.replace_range(24..28, "!!!!");
+        let mut bad_revision = sealed.clone();
+        bad_revision.replace_range(16..24, "0000000x");
+        let non_ascii = "é".repeat(40);
+        let cases = [
+            (&sealed[..39], "unlock failure E3"),
+            (bad_nonce.as_str(), "unlock failure E3"),
+            (bad_revision.as_str(), "unlock failure E3"),
+            (non_ascii.as_str(), "unlock failure E3"),
+            (&sealed[..sealed.len() - 4], "unlock failure E1"),
+            (&sealed[..41], "unlock failure E1"),
+        ];
+        for (rdb, msg) in cases {
+            let mut scratch = test_db();
+            assert_eq!(scratch.unlock_enc(rdb), msg, "{rdb:?}");
+            assert!(scratch.db_map.is_empty());
+        }
+    }
+
+    #[test]
+    fn foreign_blobs_are_rejected() {
+        let mut db = test_db();
+        db.set("mail".into(), secret("hunter2"));
+        let sealed = db.db_enc.clone();
+        let mut bad_version = sealed.clone();
+        bad_version.replace_range(..8, "99999999");
+        // An empty blob is a new database to unlock_enc, so the callers reading stored copies must reject it.
+        assert!(!AppDB::is_well_formed(""));
+        assert!(db.inspect_revision("").is_err());
+        assert!(db.inspect_revision(&bad_version).is_err());
+        assert_eq!(db.restore_revision(&bad_version), "restore failure E1");
+        let mut other_key = AppDB::new();
+        other_key.set_password_hash(&[8; 32]);
+        assert_eq!(other_key.unlock_enc(&sealed), "unlock failure E1");
+    }
+
+    #[test]
+    fn seal_round_trips() {
+        let mut db = test_db();
+        db.set("mail".into(), secret("hunter2\nuser: me"));
+        db.set("bank/ünïcode".into(), secret("\"quoted\" 🔑"));
+        let mut reopened = test_db();
+        assert_eq!(reopened.unlock_enc(&db.db_enc), "unlocked");
+        assert_eq!(reopened.db_map.len(), 2);
+        assert_eq!(reopened.db_map["mail"], "hunter2\nuser: me");
+        assert_eq!(reopened.db_map["bank/ünïcode"], "\"quoted\" 🔑");
+    }
+
+    #[test]
+    fn seal_is_stable_across_insertion_order() {
+        let entries = [("b", "2"), ("a", "1"), ("c", "3")];
+        let mut forward = test_db();
+        let mut backward = test_db();
+        for (akey, aval) in entries {
+            forward.set(akey.into(), secret(aval));
+        }
+        for (akey, aval) in entries.iter().rev() {
+            backward.set(akey.to_string(), secret(aval));
+        }
+        assert_eq!(forward.db_enc, backward.db_enc);
+        let sealed = forward.db_enc.clone();
+        forward.reseal();
+        assert_eq!(forward.db_enc, sealed);
+    }
+
+    #[test]
+    fn password_hash_compares() {
+        let db = test_db();
+        assert!(db.verify_password_hash(&[7; 32]));
+        assert!(!db.verify_password_hash(&[8; 32]));
+    }
+
+    #[test]
+    fn save_then_load_round_trips() {
+        let files = MemoryFiles::default();
+        let db = saved_db(&files);
+        assert!(files.exists(&db.db_path()));
+        assert!(files.exists(&db.db_path_archive()));
+        assert!(!files.exists(&db.db_path_hidden()));
+        assert!(!files.exists(&db.db_path_journal()));
+        let mut reopened = test_db_on(&files);
+        assert_eq!(reopened.load(), "unlocked");
+        assert_eq!(reopened.revision, "00000001");
+        assert_eq!(reopened.get("mail").as_deref().map(|aval| aval.as_str()), Some("hunter2"));
+    }
+
+    #[test]
+    fn save_stopped_before_rename_rolls_back() {
+        let files = MemoryFiles::default();
+        let db = saved_db(&files);
+        files.write(&db.db_path_journal(), "00000002").unwrap();
+        files.write(&db.db_path_hidden(), "partial").unwrap();
+        let mut reopened = test_db_on(&files);
+        assert_eq!(reopened.load(), "unlocked, rolled back interrupted save");
+        assert_eq!(reopened.revision, "00000001");
+        assert!(!files.exists(&db.db_path_hidden()));
+        assert!(!files.exists(&db.db_path_journal()));
+    }
+
+    #[test]
+    fn save_stopped_after_rename_rolls_forward() {
+        let files = MemoryFiles::default();
+        let db = saved_db(&files);
+        files.write(&db.db_path_journal(), "00000001").unwrap();
+        files.remove(&db.db_path_archive()).unwrap();
+        let mut reopened = test_db_on(&files);
+        assert_eq!(reopened.load(), "unlocked, finished interrupted save");
+        assert_eq!(files.read(&db.db_path_archive()).unwrap(), files.read(&db.db_path()).unwrap());
+        assert!(!files.exists(&db.db_path_journal()));
+    }
+
+}
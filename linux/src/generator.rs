@@ -0,0 +1,48 @@
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+
+use crate::appdb::SecretString;
+
+const ONSETS: [&str; 16] = ["b", "d", "f", "g", "h", "j", "k", "l", "m", "n", "p", "r", "s", "t", "v", "z"];
+const VOWELS: [&str; 5] = ["a", "e", "i", "o", "u"];
+const SYLLABLES_PER_WORD: usize = 3;
+const WORDS: usize = 4;
+
+pub fn pronounceable() -> (SecretString, f64) {
+    let mut password = SecretString::default();
+    for word in 0..WORDS {
+        if word > 0 {
+            password.push('-');
+        }
+        for _ in 0..SYLLABLES_PER_WORD {
+            let onset = ONSETS[random_below(ONSETS.len())];
+            // A random capital on each syllable adds a bit without hurting pronunciation.
+            if random_below(2) == 1 {
+                password.push_str(&onset.to_uppercase());
+            } else {
+                password.push_str(onset);
+            }
+            password.push_str(VOWELS[random_below(VOWELS.len())]);
+        }
+    }
+    let syllable_bits = ((ONSETS.len() * VOWELS.len() * 2) as f64).log2();
+    (password, syllable_bits * (WORDS * SYLLABLES_PER_WORD) as f64)
+}
+
+pub fn shuffle<T>(items: &mut [T]) {
+    for idx in (1..items.len()).rev() {
+        items.swap(idx, random_below(idx + 1));
+    }
+}
+
+fn random_below(bound: usize) -> usize {
+    let bound = bound as u32;
+    // Reject the top partial range so every index is equally likely.
+    let zone = u32::MAX - u32::MAX % bound;
+    loop {
+        let candidate = OsRng.next_u32();
+        if candidate < zone {
+            return (candidate % bound) as usize;
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::appdb::SecretString;
+
+pub fn pass_store_dir() -> PathBuf {
+    match std::env::var_os("PASSWORD_STORE_DIR") {
+        Some(store_dir) => PathBuf::from(store_dir),
+        None => PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(".password-store"),
+    }
+}
+
+pub fn pass_store(store_dir: &Path) -> Result<Vec<(String, SecretString)>, String> {
+    let mut gpg_files = Vec::new();
+    find_gpg_files(store_dir, &mut gpg_files).map_err(|err| err.to_string())?;
+    gpg_files.sort();
+    let mut entries = Vec::with_capacity(gpg_files.len());
+    for gpg_file in gpg_files {
+        let key = gpg_file.strip_prefix(store_dir).unwrap().with_extension("").to_string_lossy().to_string();
+        let decrypted = Command::new("gpg")
+            .args(["--quiet", "--yes", "--decrypt"])
+            .arg(&gpg_file)
+            .output()
+            .map_err(|err| err.to_string())?;
+        if !decrypted.status.success() {
+            return Err(format!("gpg failed to decrypt {key}"));
+        }
+        let aval = String::from_utf8(decrypted.stdout).map_err(|_| format!("{key} is not UTF-8"))?;
+        entries.push((key, SecretString::new(aval)));
+    }
+    Ok(entries)
+}
+
+fn find_gpg_files(dir: &Path, gpg_files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for dir_entry in std::fs::read_dir(dir)? {
+        let path = dir_entry?.path();
+        let hidden = path.file_name().is_none_or(|file_name| file_name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            find_gpg_files(&path, gpg_files)?;
+        } else if path.extension().is_some_and(|extension| extension == "gpg") {
+            gpg_files.push(path);
+        }
+    }
+    Ok(())
+}
+
+enum CsvDialect {
+    Chrome,
+    Firefox,
+    LastPass,
+}
+
+pub fn csv_file(csv_path: &Path) -> Result<Vec<(String, SecretString)>, String> {
+    let csv_text = SecretString::new(std::fs::read_to_string(csv_path).map_err(|err| err.to_string())?);
+    let mut rows = parse_csv(&csv_text).into_iter();
+    let header = rows.next().ok_or("empty csv")?;
+    let dialect = if header.iter().any(|column| column.as_str() == "httpRealm") {
+        CsvDialect::Firefox
+    } else if header.iter().any(|column| column.as_str() == "grouping") {
+        CsvDialect::LastPass
+    } else if header.iter().any(|column| column.as_str() == "name") {
+        CsvDialect::Chrome
+    } else {
+        return Err("unrecognized csv header".to_string());
+    };
+    let column = |row: &[SecretString], name: &str| -> String {
+        header.iter().position(|column| column.as_str() == name).and_then(|idx| row.get(idx)).map(|field| field.to_string()).unwrap_or_default()
+    };
+    let mut entries = Vec::new();
+    for row in rows {
+        let mut url = column(&row, "url");
+        let (name, note) = match dialect {
+            CsvDialect::Chrome => (column(&row, "name"), column(&row, "note")),
+            CsvDialect::Firefox => (url_host(&url).to_string(), String::new()),
+            CsvDialect::LastPass => {
+                // LastPass exports secure notes with the placeholder url http://sn.
+                if url == "http://sn" {
+                    url.clear();
+                }
+                let grouping = column(&row, "grouping");
+                let name = column(&row, "name");
+                (if grouping.is_empty() { name } else { format!("{grouping}/{name}") }, column(&row, "extra"))
+            }
+        };
+        let name = if name.is_empty() { url_host(&url).to_string() } else { name };
+        let username = column(&row, "username");
+        let key = if username.is_empty() { name } else { format!("{name}/{username}") };
+        // Only LastPass exports a totp column; for the other dialects it reads as empty.
+        let totp = column(&row, "totp");
+        let mut aval = column(&row, "password");
+        for (label, field) in [("username", &username), ("url", &url), ("totp", &totp)] {
+            if !field.is_empty() {
+                aval.push_str(&format!("\n{label}: {field}"));
+            }
+        }
+        if !note.is_empty() {
+            aval.push('\n');
+            aval.push_str(&note);
+        }
+        entries.push((key, SecretString::new(aval)));
+    }
+    Ok(entries)
+}
+
+fn url_host(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    without_scheme.split(['/', ':', '?', '#']).next().unwrap_or_default()
+}
+
+fn parse_csv(csv_text: &str) -> Vec<Vec<SecretString>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = SecretString::default();
+    let mut quoted = false;
+    let mut chars = csv_text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use lettre::address::AddressError;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+
+pub const CONFIG_PATH: &str = "/secrets/smtp.json";
+
+// Most providers reject messages over 20-25 MB, and base64 adds a third on top of the attachment.
+const MAX_ATTACHMENT_BYTES: usize = 10 * 1024 * 1024;
+const MAX_BACKUP_BYTES: usize = 50 * 1024 * 1024;
+
+pub fn is_configured() -> bool {
+    std::path::Path::new(CONFIG_PATH).exists()
+}
+
+// Mails db_enc to the configured address, split across several messages if needed, and returns how many were sent.
+pub fn send_backup(db_id: &str, revision: &str, db_enc: &str) -> Result<usize, String> {
+    if db_enc.len() > MAX_BACKUP_BYTES {
+        return Err(format!("backup exceeds {} MiB", MAX_BACKUP_BYTES / 1024 / 1024));
+    }
+    let config: HashMap<String, String> = serde_json::from_str(&std::fs::read_to_string(CONFIG_PATH).map_err(|err| err.to_string())?)
+        .map_err(|err| err.to_string())?;
+    let field = |name: &str| config.get(name).cloned().ok_or_else(|| format!("smtp config has no {name}"));
+    let host = field("host")?;
+    let to: Mailbox = field("to")?.parse().map_err(|err: AddressError| err.to_string())?;
+    let from: Mailbox = config.get("from").unwrap_or(&field("to")?).parse().map_err(|err: AddressError| err.to_string())?;
+    let credentials = Credentials::new(field("username")?, field("password")?);
+    // Port 587 upgrades with STARTTLS; anything else uses implicit TLS.
+    let mailer = match config.get("port").and_then(|port| port.parse::<u16>().ok()) {
+        Some(587) => SmtpTransport::starttls_relay(&host),
+        Some(port) => SmtpTransport::relay(&host).map(|builder| builder.port(port)),
+        None => SmtpTransport::relay(&host),
+    }.map_err(|err| err.to_string())?
+        .credentials(credentials)
+        .build();
+    // The envelope is base64 text, so splitting on bytes never cuts a character.
+    let parts: Vec<&[u8]> = db_enc.as_bytes().chunks(MAX_ATTACHMENT_BYTES).collect();
+    let file_name = format!("digisafe_{db_id}_{revision}.db");
+    for (index, part) in parts.iter().enumerate() {
+        let (subject, part_name) = if parts.len() == 1 {
+            (format!("DigiSafe backup {db_id} revision {revision}"), file_name.clone())
+        } else {
+            (format!("DigiSafe backup {db_id} revision {revision} part {}/{}", index + 1, parts.len()), format!("{file_name}.part{}", index + 1))
+        };
+        let text = "Encrypted DigiSafe database backup. Restore it with Restore Snapshot after joining any parts in order.";
+        let message = Message::builder()
+            .from(from.clone())
+            .to(to.clone())
+            .subject(subject)
+            .multipart(MultiPart::mixed()
+                .singlepart(SinglePart::plain(text.to_owned()))
+                .singlepart(Attachment::new(part_name).body(part.to_vec(), ContentType::parse("application/octet-stream").unwrap())))
+            .map_err(|err| err.to_string())?;
+        mailer.send(&message).map_err(|err| err.to_string())?;
+    }
+    Ok(parts.len())
+}
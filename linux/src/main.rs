@@ -1,12 +1,22 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use gtk::prelude::*;
 
 mod appdb;
+mod generator;
+mod import;
+mod mail;
+mod remote;
+mod settings;
+mod store;
 
 fn main() {
+    // Under memory pressure, prefer being OOM-killed over keeping key material around.
+    let _ = std::fs::write("/proc/self/oom_score_adj", "1000");
     let app_id = "com.digisafe.db";
     let app = gtk::Application::builder().application_id(app_id).build();
     app.connect_activate(build_ui);
@@ -16,8 +26,19 @@ fn main() {
 fn build_ui(app: &gtk::Application) {
 
     let db = Arc::new(RwLock::new(appdb::AppDB::new()));
-    let status_bar = Rc::new(RefCell::new(gtk::Statusbar::new()));
     let side_margin = 20;
+    let status_bar = Rc::new(RefCell::new(gtk::Statusbar::builder()
+        .hexpand(true)
+        .build()));
+    let sync_label = gtk::Label::builder()
+        .margin_end(side_margin)
+        .build();
+    let progress_bar = gtk::ProgressBar::builder()
+        .valign(gtk::Align::Center)
+        .margin_end(side_margin)
+        .width_request(200)
+        .visible(false)
+        .build();
 
     let main_box = Rc::new(RefCell::new(gtk::Box::builder()
         .orientation(gtk::Orientation::Vertical)
@@ -39,49 +60,103 @@ fn build_ui(app: &gtk::Application) {
         .margin_end(side_margin)
         .height_request(400)
         .tooltip_text("Value Text")
+        .accepts_tab(false)
         .build()));
+    // Tooltips are only descriptions, so give the inputs accessible names too.
+    key_entry.borrow().update_property(&[gtk::accessible::Property::Label("Key")]);
+    val_entry.borrow().update_property(&[gtk::accessible::Property::Label("Value"), gtk::accessible::Property::MultiLine(true)]);
+
+    // The key last loaded by Get; None while creating a new entry.
+    let editing_key = Rc::new(RefCell::new(None::<String>));
+    let set_button = gtk::Button::builder()
+        .label("Add")
+        .margin_top(20)
+        .margin_bottom(20)
+        .margin_start(10)
+        .margin_end(10)
+        .build();
+
+    let new_button = gtk::Button::builder()
+        .label("New")
+        .tooltip_text("Start a new entry with a generated password (Ctrl+N)")
+        .action_name("win.new-entry")
+        .margin_top(20)
+        .margin_bottom(20)
+        .margin_start(20)
+        .margin_end(10)
+        .build();
+    new_button.set_size_request(140, 20);
 
     let get_button = gtk::Button::builder()
         .label("Get")
         .margin_top(20)
         .margin_bottom(20)
         .margin_start(10)
-        .margin_end(20)
+        .margin_end(10)
         .build();
     let db_get = Arc::clone(&db);
     let key_get = Rc::clone(&key_entry);
     let val_get = Rc::clone(&val_entry);
     let main_box2 = Rc::clone(&main_box);
+    let editing_key2 = Rc::clone(&editing_key);
+    let set_button2 = set_button.clone();
     get_button.connect_clicked(move |_| {
         main_box2.borrow().set_sensitive(false);
         let key = key_get.borrow().text().to_string();
         if let Some(val) = db_get.write().unwrap().get(&key) {
             val_get.borrow_mut().buffer().set_text(&val);
+            set_entry_mode(&set_button2, &editing_key2, Some(key));
         } else {
             val_get.borrow_mut().buffer().set_text("");
+            set_entry_mode(&set_button2, &editing_key2, None);
         }
         main_box2.borrow().set_sensitive(true);
     });
     get_button.set_size_request(140, 20);
 
-    let set_button = gtk::Button::builder()
-        .label("Set")
-        .margin_top(20)
-        .margin_bottom(20)
-        .margin_start(10)
-        .margin_end(10)
-        .build();
     let db_set = Arc::clone(&db);
     let key_set = Rc::clone(&key_entry);
     let val_set = Rc::clone(&val_entry);
     let main_box2 = Rc::clone(&main_box);
-    set_button.connect_clicked(move |_| {
-        main_box2.borrow().set_sensitive(false);
+    let status_bar2 = Rc::clone(&status_bar);
+    let editing_key2 = Rc::clone(&editing_key);
+    set_button.connect_clicked(move |set_button| {
         let key = key_set.borrow().text().to_string();
         let bounds = val_set.borrow().buffer().bounds();
-        let val = val_set.borrow().buffer().text(&bounds.0, &bounds.1, false).to_string();
-        db_set.write().unwrap().set(key, val);
+        let val = appdb::SecretString::new(val_set.borrow().buffer().text(&bounds.0, &bounds.1, false).to_string());
+        if key.is_empty() {
+            status_bar2.borrow().push(0, "key is empty");
+            return;
+        }
+        let editing = editing_key2.borrow().clone();
+        let creating = editing.is_none();
+        let mut db = db_set.write().unwrap();
+        let exists = db.keys().contains(&key);
+        let renamed_from = editing.filter(|old_key| *old_key != key);
+        if exists && (creating || renamed_from.is_some()) {
+            status_bar2.borrow().push(0, "key already exists, use Get to edit it");
+            return;
+        }
+        if let Some(old_key) = renamed_from {
+            drop(db);
+            key_changed_dialog(set_button, Arc::clone(&db_set), Rc::clone(&status_bar2), Rc::clone(&editing_key2), Rc::clone(&val_set), old_key, key);
+            return;
+        }
+        if let Some(old_val) = db.get(&key).filter(|_| !creating) {
+            let diff = entry_diff(&old_val, &val);
+            if diff.is_empty() {
+                status_bar2.borrow().push(0, "no changes");
+                return;
+            }
+            drop(db);
+            update_dialog(set_button, Arc::clone(&db_set), Rc::clone(&status_bar2), Rc::clone(&editing_key2), key, val, &diff);
+            return;
+        }
+        main_box2.borrow().set_sensitive(false);
+        db.set(key.clone(), val);
         main_box2.borrow().set_sensitive(true);
+        status_bar2.borrow().push(0, if creating { "entry added" } else { "entry updated" });
+        set_entry_mode(set_button, &editing_key2, Some(key));
     });
     set_button.set_size_request(140, 20);
 
@@ -92,24 +167,19 @@ fn build_ui(app: &gtk::Application) {
         .margin_start(10)
         .margin_end(20)
         .build();
-    let (save_sender, save_receiver) = gtk::glib::MainContext::channel::<String>(gtk::glib::PRIORITY_DEFAULT);
-    let status_bar2 = Rc::clone(&status_bar);
-    let main_box2 = Rc::clone(&main_box);
-    save_receiver.attach(None, move|msg| {
-        status_bar2.borrow().push(0, &msg);
-        main_box2.borrow().set_sensitive(true);
-        gtk::glib::Continue(true)
-    });
+    let (save_sender, save_receiver) = gtk::glib::MainContext::channel::<(f64, String)>(gtk::glib::PRIORITY_DEFAULT);
+    let (save_queue, save_requests) = std::sync::mpsc::channel::<()>();
     let db_save = Arc::clone(&db);
-    let main_box2 = Rc::clone(&main_box);
-    save_button.connect_clicked(move |_| {
-        main_box2.borrow().set_sensitive(false);
-        let db_save = Arc::clone(&db_save);
-        let save_sender = save_sender.clone();
-        std::thread::spawn(move || {
-            let msg = db_save.write().unwrap().save();
-            save_sender.send(msg).expect("save sender error");
-        });
+    std::thread::spawn(move || {
+        while save_requests.recv().is_ok() {
+            // Requests queued while the previous save ran are covered by this one.
+            while save_requests.try_recv().is_ok() {}
+            let progress_sender = save_sender.clone();
+            let msg = db_save.write().unwrap().save_with_progress(|fraction, msg| {
+                progress_sender.send((fraction, msg.to_owned())).expect("save sender error");
+            });
+            save_sender.send((1.0, msg)).expect("save sender error");
+        }
     });
     save_button.set_size_request(140, 20);
 
@@ -119,80 +189,1133 @@ fn build_ui(app: &gtk::Application) {
         .build();
     main_box.borrow().append(&*key_entry.borrow());
     main_box.borrow().append(&*val_entry.borrow());
+    button_box.append(&new_button);
     button_box.append(&get_button);
     button_box.append(&set_button);
     button_box.append(&save_button);
     main_box.borrow().append(&button_box);
 
     status_bar.borrow().push(0, "locked");
-    main_box.borrow().append(&*status_bar.borrow());
+    let status_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .build();
+    status_box.append(&*status_bar.borrow());
+    status_box.append(&sync_label);
+    status_box.append(&progress_bar);
+    main_box.borrow().append(&status_box);
 
     main_box.borrow().set_sensitive(false);
 
+    let menu = gtk::gio::Menu::new();
+    menu.append(Some("New Entry"), Some("win.new-entry"));
+    menu.append(Some("Generate Password"), Some("win.generate"));
+    menu.append(Some("Import from pass"), Some("win.import-pass"));
+    menu.append(Some("Import CSV…"), Some("win.import-csv"));
+    menu.append(Some("Export JSON…"), Some("win.export-json"));
+    menu.append(Some("Restore Snapshot…"), Some("win.restore-snapshot"));
+    menu.append(Some("Time Machine…"), Some("win.time-machine"));
+    menu.append(Some("Test Remote Restore"), Some("win.test-restore"));
+    menu.append(Some("Email Backup"), Some("win.email-backup"));
+    menu.append(Some("Create Recovery Drive…"), Some("win.recovery-drive"));
+    menu.append(Some("Database Info"), Some("win.info"));
+    menu.append(Some("Settings"), Some("win.settings"));
+    menu.append(Some("Export Settings…"), Some("win.export-settings"));
+    menu.append(Some("Import Settings…"), Some("win.import-settings"));
+    let menu_button = gtk::MenuButton::builder()
+        .icon_name("open-menu-symbolic")
+        .menu_model(&menu)
+        .tooltip_text("Menu")
+        .build();
+    menu_button.update_property(&[gtk::accessible::Property::Label("Menu")]);
+    let unlock_spinner = gtk::Spinner::builder()
+        .visible(false)
+        .build();
+    let cancel_unlock_button = gtk::Button::builder()
+        .label("Cancel")
+        .tooltip_text("Cancel unlock and re-enter the password")
+        .visible(false)
+        .build();
+    let header_bar = gtk::HeaderBar::new();
+    header_bar.pack_start(&unlock_spinner);
+    header_bar.pack_start(&cancel_unlock_button);
+    header_bar.pack_end(&menu_button);
+    let lock_button = gtk::Button::builder()
+        .icon_name("system-lock-screen-symbolic")
+        .tooltip_text("Lock (Ctrl+L)")
+        .action_name("win.lock")
+        .build();
+    lock_button.update_property(&[gtk::accessible::Property::Label("Lock")]);
+    header_bar.pack_end(&lock_button);
+
+    let (width, height) = settings::get("window_size")
+        .and_then(|size| {
+            let (width, height) = size.split_once('x')?;
+            Some((width.parse::<i32>().ok()?, height.parse::<i32>().ok()?))
+        })
+        .unwrap_or((800, 600));
     let window = Rc::new(gtk::ApplicationWindow::builder()
         .application(app)
-        .default_width(800)
-        .default_height(600)
+        .default_width(width)
+        .default_height(height)
+        .maximized(settings::get("window_maximized").is_some())
         .title("DigiSafe")
+        .titlebar(&header_bar)
         .child(&*main_box.borrow())
         .visible(true)
         .build());
     window.present();
+    // GTK4 leaves placement to the compositor, so only the size and maximized state are kept.
+    window.connect_close_request(|window| {
+        let (width, height) = window.default_size();
+        let _ = settings::set("window_size", &format!("{width}x{height}"))
+            .and_then(|_| settings::set("window_maximized", if window.is_maximized() { "true" } else { "" }));
+        gtk::Inhibit(false)
+    });
+
+    let settings_action = gtk::gio::SimpleAction::new("settings", None);
+    let window2 = Rc::clone(&window);
+    let status_bar2 = Rc::clone(&status_bar);
+    settings_action.connect_activate(move |_, _| {
+        settings_dialog(&*window2, Rc::clone(&status_bar2));
+    });
+    window.add_action(&settings_action);
+
+    let export_settings_action = gtk::gio::SimpleAction::new("export-settings", None);
+    let window2 = Rc::clone(&window);
+    let status_bar2 = Rc::clone(&status_bar);
+    export_settings_action.connect_activate(move |_, _| {
+        let status_bar = Rc::clone(&status_bar2);
+        run_native_chooser(&*window2, "Export Settings", gtk::FileChooserAction::Save, "Export", |chooser| {
+            chooser.set_current_name("digisafe_settings.json");
+        }, move |path| {
+            let msg = match settings::export(&path) {
+                Ok(()) => "settings exported".to_owned(),
+                Err(err) => format!("settings export failure: {err}"),
+            };
+            status_bar.borrow().push(0, &msg);
+        });
+    });
+    window.add_action(&export_settings_action);
+
+    let import_settings_action = gtk::gio::SimpleAction::new("import-settings", None);
+    let window2 = Rc::clone(&window);
+    let status_bar2 = Rc::clone(&status_bar);
+    import_settings_action.connect_activate(move |_, _| {
+        import_settings_dialog(&*window2, Rc::clone(&status_bar2), Rc::new(|| {}));
+    });
+    window.add_action(&import_settings_action);
+
+    let (import_sender, import_receiver) = gtk::glib::MainContext::channel::<String>(gtk::glib::PRIORITY_DEFAULT);
+    let status_bar2 = Rc::clone(&status_bar);
+    let main_box2 = Rc::clone(&main_box);
+    import_receiver.attach(None, move|msg| {
+        status_bar2.borrow().push(0, &msg);
+        main_box2.borrow().set_sensitive(true);
+        gtk::glib::Continue(true)
+    });
+    let import_action = gtk::gio::SimpleAction::new("import-pass", None);
+    import_action.set_enabled(false);
+    let db2 = Arc::clone(&db);
+    let status_bar2 = Rc::clone(&status_bar);
+    let main_box2 = Rc::clone(&main_box);
+    let import_sender2 = import_sender.clone();
+    import_action.connect_activate(move |_, _| {
+        main_box2.borrow().set_sensitive(false);
+        status_bar2.borrow().push(0, "importing from pass");
+        let db = Arc::clone(&db2);
+        let import_sender = import_sender2.clone();
+        std::thread::spawn(move || {
+            let msg = match import::pass_store(&import::pass_store_dir()) {
+                Ok(entries) => db.write().unwrap().import(entries),
+                Err(err) => format!("import failure: {err}"),
+            };
+            import_sender.send(msg).expect("import sender error");
+        });
+    });
+    window.add_action(&import_action);
 
-    let (unlock_sender, unlock_receiver) = gtk::glib::MainContext::channel::<String>(gtk::glib::PRIORITY_DEFAULT);
+    let import_csv_action = gtk::gio::SimpleAction::new("import-csv", None);
+    import_csv_action.set_enabled(false);
     let window2 = Rc::clone(&window);
     let db2 = Arc::clone(&db);
+    let status_bar2 = Rc::clone(&status_bar);
     let main_box2 = Rc::clone(&main_box);
-    gtk::glib::MainContext::default().spawn_local(unlock_dialog(window2, db2, unlock_sender));
-    unlock_receiver.attach(None, move|msg| {
-        status_bar.borrow().push(0, &msg);
-        if msg == "unlocked" {
+    import_csv_action.connect_activate(move |_, _| {
+        let db = Arc::clone(&db2);
+        let status_bar = Rc::clone(&status_bar2);
+        let main_box = Rc::clone(&main_box2);
+        let import_sender = import_sender.clone();
+        run_native_chooser(&*window2, "Import CSV", gtk::FileChooserAction::Open, "Import", |chooser| {
+            let csv_filter = gtk::FileFilter::new();
+            csv_filter.set_name(Some("Chrome, Firefox or LastPass CSV"));
+            csv_filter.add_pattern("*.csv");
+            chooser.add_filter(&csv_filter);
+        }, move |path| {
+            main_box.borrow().set_sensitive(false);
+            status_bar.borrow().push(0, "importing csv");
+            let db = Arc::clone(&db);
+            let import_sender = import_sender.clone();
+            std::thread::spawn(move || {
+                let msg = match import::csv_file(&path) {
+                    Ok(entries) => db.write().unwrap().import(entries),
+                    Err(err) => format!("import failure: {err}"),
+                };
+                import_sender.send(msg).expect("import sender error");
+            });
+        });
+    });
+    window.add_action(&import_csv_action);
+
+    let save_action = gtk::gio::SimpleAction::new("save", None);
+    save_action.set_enabled(false);
+    let save_button2 = save_button.clone();
+    save_action.connect_activate(move |_, _| {
+        save_button2.emit_clicked();
+    });
+    window.add_action(&save_action);
+
+    let generate_action = gtk::gio::SimpleAction::new("generate", None);
+    generate_action.set_enabled(false);
+    let val_entry2 = Rc::clone(&val_entry);
+    let status_bar2 = Rc::clone(&status_bar);
+    generate_action.connect_activate(move |_, _| {
+        let (password, entropy_bits) = generator::pronounceable();
+        // Replace only the first line, which holds the password, and keep any notes below it.
+        let buffer = val_entry2.borrow().buffer();
+        let mut first_line_start = buffer.start_iter();
+        let mut first_line_end = buffer.start_iter();
+        if !first_line_end.ends_line() {
+            first_line_end.forward_to_line_end();
+        }
+        buffer.delete(&mut first_line_start, &mut first_line_end);
+        buffer.insert(&mut first_line_start, &password);
+        status_bar2.borrow().push(0, &format!("generated password ({entropy_bits:.0} bits)"));
+    });
+    window.add_action(&generate_action);
+
+    let new_entry_action = gtk::gio::SimpleAction::new("new-entry", None);
+    new_entry_action.set_enabled(false);
+    let window2 = Rc::clone(&window);
+    let key_entry2 = Rc::clone(&key_entry);
+    let val_entry2 = Rc::clone(&val_entry);
+    let editing_key2 = Rc::clone(&editing_key);
+    let set_button2 = set_button.clone();
+    new_entry_action.connect_activate(move |_, _| {
+        key_entry2.borrow().set_text("");
+        val_entry2.borrow().buffer().set_text("");
+        set_entry_mode(&set_button2, &editing_key2, None);
+        key_entry2.borrow().grab_focus();
+        WidgetExt::activate_action(&*window2, "win.generate", None).ok();
+    });
+    window.add_action(&new_entry_action);
+    app.set_accels_for_action("win.new-entry", &["<Control>n"]);
+
+    let palette_action = gtk::gio::SimpleAction::new("command-palette", None);
+    let window2 = Rc::clone(&window);
+    palette_action.connect_activate(move |_, _| {
+        command_palette(&window2);
+    });
+    window.add_action(&palette_action);
+    app.set_accels_for_action("win.command-palette", &["<Control><Shift>p"]);
+
+    let key_store = gtk::ListStore::new(&[String::static_type()]);
+    let key_completion = gtk::EntryCompletion::builder()
+        .model(&key_store)
+        .text_column(0)
+        .minimum_key_length(1)
+        .build();
+    key_completion.connect_match_selected(|completion, model, iter| {
+        let key = model.get::<String>(iter, 0);
+        if let Some(entry) = completion.entry() {
+            entry.set_text(&key);
+            entry.emit_activate();
+        }
+        gtk::Inhibit(true)
+    });
+    key_entry.borrow().set_completion(Some(&key_completion));
+    let key_focus = gtk::EventControllerFocus::new();
+    let db2 = Arc::clone(&db);
+    let key_store2 = key_store.clone();
+    key_focus.connect_enter(move |_| {
+        // Refresh on focus so keys added by Set or an import are offered.
+        if let Ok(mut db) = db2.try_write() {
+            key_store2.clear();
+            for key in db.keys() {
+                key_store2.set(&key_store2.append(), &[(0, &key)]);
+            }
+        }
+    });
+    key_entry.borrow().add_controller(&key_focus);
+    let copy_allowed = Rc::new(Cell::new(true));
+    let clipboard_in_use = Rc::new(Cell::new(false));
+    let clipboard_in_use2 = Rc::clone(&clipboard_in_use);
+    let status_bar2 = Rc::clone(&status_bar);
+    window.clipboard().connect_changed(move |clipboard| {
+        // Losing ownership means the password was replaced, possibly by a clipboard manager taking a copy,
+        // so clear now rather than on the next tick. The periodic clear would wipe the new contents anyway.
+        if !clipboard.is_local() && clipboard_in_use2.replace(false) {
+            clipboard.set_text("");
+            status_bar2.borrow().push(0, "clipboard cleared");
+        }
+    });
+    let window2 = Rc::clone(&window);
+    let db2 = Arc::clone(&db);
+    let val_entry2 = Rc::clone(&val_entry);
+    let status_bar2 = Rc::clone(&status_bar);
+    let copy_allowed2 = Rc::clone(&copy_allowed);
+    let clipboard_in_use2 = Rc::clone(&clipboard_in_use);
+    let editing_key2 = Rc::clone(&editing_key);
+    let set_button2 = set_button.clone();
+    key_entry.borrow().connect_activate(move |key_entry| {
+        if let Some(val) = db2.write().unwrap().get(&key_entry.text()) {
+            val_entry2.borrow().buffer().set_text(&val);
+            set_entry_mode(&set_button2, &editing_key2, Some(key_entry.text().to_string()));
+            // A refusal only holds while a clipboard manager is still running.
+            if !copy_allowed2.get() && !clipboard_managers().is_empty() {
+                status_bar2.borrow().push(0, "copy blocked, clipboard manager running");
+                return;
+            }
+            copy_allowed2.set(true);
+            // Like pass, the first line of the value is the password.
+            window2.clipboard().set_text(val.lines().next().unwrap_or_default());
+            clipboard_in_use2.set(true);
+            window2.minimize();
+        }
+    });
+
+    let export_action = gtk::gio::SimpleAction::new("export-json", None);
+    export_action.set_enabled(false);
+    let window2 = Rc::clone(&window);
+    let db2 = Arc::clone(&db);
+    let status_bar2 = Rc::clone(&status_bar);
+    export_action.connect_activate(move |_, _| {
+        let window = Rc::clone(&window2);
+        let db = Arc::clone(&db2);
+        let status_bar = Rc::clone(&status_bar2);
+        reauth_dialog(&*window2, Arc::clone(&db2), Rc::clone(&status_bar2), Rc::new(move |_, _| {
+            export_dialog(&*window, Arc::clone(&db), Rc::clone(&status_bar));
+        }));
+    });
+    window.add_action(&export_action);
+
+    let restore_action = gtk::gio::SimpleAction::new("restore-snapshot", None);
+    restore_action.set_enabled(false);
+    let window2 = Rc::clone(&window);
+    let db2 = Arc::clone(&db);
+    let status_bar2 = Rc::clone(&status_bar);
+    restore_action.connect_activate(move |_, _| {
+        let db = Arc::clone(&db2);
+        let status_bar = Rc::clone(&status_bar2);
+        run_native_chooser(&*window2, "Restore Snapshot", gtk::FileChooserAction::Open, "Restore", |chooser| {
+            if let Ok(snapshot_dir) = std::fs::canonicalize(db2.read().unwrap().snapshot_dir()) {
+                let _ = chooser.set_current_folder(Some(&gtk::gio::File::for_path(snapshot_dir)));
+            }
+        }, move |path| {
+            let msg = db.write().unwrap().restore_snapshot(&path);
+            status_bar.borrow().push(0, &msg);
+        });
+    });
+    window.add_action(&restore_action);
+
+    let time_machine_action = gtk::gio::SimpleAction::new("time-machine", None);
+    time_machine_action.set_enabled(false);
+    let window2 = Rc::clone(&window);
+    let db2 = Arc::clone(&db);
+    let status_bar2 = Rc::clone(&status_bar);
+    time_machine_action.connect_activate(move |_, _| {
+        time_machine_dialog(&*window2, Arc::clone(&db2), Rc::clone(&status_bar2));
+    });
+    window.add_action(&time_machine_action);
+
+    let (test_restore_sender, test_restore_receiver) = gtk::glib::MainContext::channel::<String>(gtk::glib::PRIORITY_DEFAULT);
+    let status_bar2 = Rc::clone(&status_bar);
+    test_restore_receiver.attach(None, move|msg| {
+        status_bar2.borrow().push(0, &msg);
+        gtk::glib::Continue(true)
+    });
+    let test_restore_action = gtk::gio::SimpleAction::new("test-restore", None);
+    test_restore_action.set_enabled(false);
+    let db2 = Arc::clone(&db);
+    let status_bar2 = Rc::clone(&status_bar);
+    test_restore_action.connect_activate(move |_, _| {
+        status_bar2.borrow().push(0, "testing remote restore");
+        let db = Arc::clone(&db2);
+        let test_restore_sender = test_restore_sender.clone();
+        std::thread::spawn(move || {
+            let msg = db.read().unwrap().test_restore();
+            test_restore_sender.send(msg).expect("test restore sender error");
+        });
+    });
+    window.add_action(&test_restore_action);
+
+    let (email_backup_sender, email_backup_receiver) = gtk::glib::MainContext::channel::<String>(gtk::glib::PRIORITY_DEFAULT);
+    let status_bar2 = Rc::clone(&status_bar);
+    email_backup_receiver.attach(None, move|msg| {
+        status_bar2.borrow().push(0, &msg);
+        gtk::glib::Continue(true)
+    });
+    let email_backup_action = gtk::gio::SimpleAction::new("email-backup", None);
+    email_backup_action.set_enabled(false);
+    let db2 = Arc::clone(&db);
+    let status_bar2 = Rc::clone(&status_bar);
+    email_backup_action.connect_activate(move |_, _| {
+        status_bar2.borrow().push(0, "emailing backup");
+        let db = Arc::clone(&db2);
+        let email_backup_sender = email_backup_sender.clone();
+        std::thread::spawn(move || {
+            let msg = db.read().unwrap().email_backup();
+            email_backup_sender.send(msg).expect("email backup sender error");
+        });
+    });
+    window.add_action(&email_backup_action);
+
+    let recovery_action = gtk::gio::SimpleAction::new("recovery-drive", None);
+    recovery_action.set_enabled(false);
+    let window2 = Rc::clone(&window);
+    let db2 = Arc::clone(&db);
+    let status_bar2 = Rc::clone(&status_bar);
+    recovery_action.connect_activate(move |_, _| {
+        recovery_dialog(&*window2, Arc::clone(&db2), Rc::clone(&status_bar2));
+    });
+    window.add_action(&recovery_action);
+
+    let info_action = gtk::gio::SimpleAction::new("info", None);
+    info_action.set_enabled(false);
+    let window2 = Rc::clone(&window);
+    let db2 = Arc::clone(&db);
+    let status_bar2 = Rc::clone(&status_bar);
+    info_action.connect_activate(move |_, _| {
+        info_dialog(&*window2, Arc::clone(&db2), Rc::clone(&status_bar2));
+    });
+    window.add_action(&info_action);
+
+    let (unlock_sender, unlock_receiver) = gtk::glib::MainContext::channel::<(u32, String)>(gtk::glib::PRIORITY_DEFAULT);
+    let unlock_attempt = Arc::new(AtomicU32::new(0));
+    let unlock_started = Rc::new(Cell::new(None::<Instant>));
+    let window2 = Rc::clone(&window);
+    let db2 = Arc::clone(&db);
+    let unlock_attempt2 = Arc::clone(&unlock_attempt);
+    let unlock_started2 = Rc::clone(&unlock_started);
+    let spawn_unlock: Rc<dyn Fn()> = Rc::new(move || {
+        gtk::glib::MainContext::default().spawn_local(unlock_dialog(Rc::clone(&window2), Arc::clone(&db2), unlock_sender.clone(), Arc::clone(&unlock_attempt2), Rc::clone(&unlock_started2)));
+    });
+    if settings::get("setup_done").is_none() && !remote::is_configured() {
+        first_run_dialog(&*window, Rc::clone(&status_bar), Rc::clone(&spawn_unlock));
+    } else {
+        spawn_unlock();
+    }
+
+    let spawn_unlock2 = Rc::clone(&spawn_unlock);
+    let unlock_attempt2 = Arc::clone(&unlock_attempt);
+    let unlock_started2 = Rc::clone(&unlock_started);
+    let status_bar2 = Rc::clone(&status_bar);
+    let unlock_spinner2 = unlock_spinner.clone();
+    cancel_unlock_button.connect_clicked(move |cancel_unlock_button| {
+        // The abandoned derivation finishes in the background and is discarded; the next attempt waits for it.
+        unlock_attempt2.fetch_add(1, Ordering::SeqCst);
+        unlock_started2.set(None);
+        unlock_spinner2.stop();
+        unlock_spinner2.set_visible(false);
+        cancel_unlock_button.set_visible(false);
+        status_bar2.borrow().push(0, "unlock cancelled");
+        spawn_unlock2();
+    });
+
+    let unlock_started2 = Rc::clone(&unlock_started);
+    let status_bar2 = Rc::clone(&status_bar);
+    let window2 = Rc::clone(&window);
+    let watchdog_fired = Cell::new(false);
+    gtk::glib::timeout_add_seconds_local(1, move|| {
+        if let Some(started) = unlock_started2.get() {
+            unlock_spinner.set_visible(true);
+            unlock_spinner.start();
+            cancel_unlock_button.set_visible(true);
+            status_bar2.borrow().push(0, &format!("unlocking ({}s)", started.elapsed().as_secs()));
+            if started.elapsed().as_secs() >= UNLOCK_WATCHDOG_SECS && !watchdog_fired.replace(true) {
+                slow_unlock_dialog(&*window2, cancel_unlock_button.clone());
+            }
+        } else {
+            unlock_spinner.stop();
+            unlock_spinner.set_visible(false);
+            cancel_unlock_button.set_visible(false);
+            watchdog_fired.set(false);
+        }
+        gtk::glib::Continue(true)
+    });
+
+    let db2 = Arc::clone(&db);
+    let status_bar2 = Rc::clone(&status_bar);
+    gtk::glib::timeout_add_seconds_local(3600, move|| {
+        // Snapshots hold the sealed in-memory state, so they include unsaved changes.
+        if let Ok(db) = db2.try_read() {
+            let msg = db.snapshot();
+            if msg.starts_with("snapshot failure") {
+                status_bar2.borrow().push(0, &msg);
+            }
+        }
+        gtk::glib::Continue(true)
+    });
+
+    let lock_action = gtk::gio::SimpleAction::new("lock", None);
+    lock_action.set_enabled(false);
+    let unlock_actions = Rc::new([save_action, new_entry_action, generate_action, export_action, import_action, import_csv_action, restore_action, time_machine_action, test_restore_action, email_backup_action, recovery_action, info_action, lock_action.clone()]);
+    let sync_source = Rc::new(RefCell::new(None::<gtk::glib::SourceId>));
+
+    // The save thread holds the database through the upload, so everything that reads it waits for the save.
+    // Locking stays available; it waits for the save on its own.
+    let save_attempt = Rc::new(Cell::new(0));
+    let save_attempt2 = Rc::clone(&save_attempt);
+    let unlock_attempt2 = Arc::clone(&unlock_attempt);
+    let main_box2 = Rc::clone(&main_box);
+    let unlock_actions2 = Rc::clone(&unlock_actions);
+    save_button.connect_clicked(move |_| {
+        save_attempt2.set(unlock_attempt2.load(Ordering::SeqCst));
+        main_box2.borrow().set_sensitive(false);
+        for action in unlock_actions2.iter().filter(|action| action.name() != "lock") {
+            action.set_enabled(false);
+        }
+        save_queue.send(()).expect("save queue error");
+    });
+    let unlock_attempt2 = Arc::clone(&unlock_attempt);
+    let status_bar2 = Rc::clone(&status_bar);
+    let main_box2 = Rc::clone(&main_box);
+    let unlock_actions2 = Rc::clone(&unlock_actions);
+    save_receiver.attach(None, move|(fraction, msg)| {
+        status_bar2.borrow().push(0, &msg);
+        if fraction < 1.0 {
+            progress_bar.set_visible(true);
+            progress_bar.set_fraction(fraction);
+        } else {
+            progress_bar.set_visible(false);
+            // A lock during the save leaves the form disabled until the next unlock.
+            if save_attempt.get() == unlock_attempt2.load(Ordering::SeqCst) {
+                main_box2.borrow().set_sensitive(true);
+                for action in unlock_actions2.iter() {
+                    action.set_enabled(true);
+                }
+            }
+        }
+        gtk::glib::Continue(true)
+    });
+
+    let window2 = Rc::clone(&window);
+    let db2 = Arc::clone(&db);
+    let main_box2 = Rc::clone(&main_box);
+    let spawn_unlock2 = Rc::clone(&spawn_unlock);
+    let unlock_attempt2 = Arc::clone(&unlock_attempt);
+    let unlock_started2 = Rc::clone(&unlock_started);
+    let status_bar2 = Rc::clone(&status_bar);
+    let unlock_actions2 = Rc::clone(&unlock_actions);
+    let sync_source2 = Rc::clone(&sync_source);
+    let reset_pending = Rc::new(Cell::new(false));
+    let lock_db: Rc<dyn Fn()> = Rc::new(move || {
+        // Discard any unlock still deriving its key so it cannot land after the lock.
+        unlock_attempt2.fetch_add(1, Ordering::SeqCst);
+        unlock_started2.set(None);
+        key_entry.borrow().set_text("");
+        val_entry.borrow().buffer().set_text("");
+        set_entry_mode(&set_button, &editing_key, None);
+        key_store.clear();
+        main_box2.borrow().set_sensitive(false);
+        for action in unlock_actions2.iter() {
+            action.set_enabled(false);
+        }
+        if let Some(source) = sync_source2.take() {
+            source.remove();
+        }
+        window2.clipboard().set_text("");
+        status_bar2.borrow().push(0, "locked");
+        if reset_pending.replace(true) {
+            return;
+        }
+        // A save or unlock may hold the database through network I/O, so never block the UI waiting for it.
+        let db = Arc::clone(&db2);
+        let spawn_unlock = Rc::clone(&spawn_unlock2);
+        let reset_pending = Rc::clone(&reset_pending);
+        gtk::glib::timeout_add_local(Duration::from_millis(100), move|| {
+            match db.try_write() {
+                Ok(mut db) => {
+                    // Dropping the old database locks it and zeroizes the password hash; unsaved changes are lost.
+                    *db = appdb::AppDB::new();
+                    drop(db);
+                    reset_pending.set(false);
+                    spawn_unlock();
+                    gtk::glib::Continue(false)
+                }
+                Err(_) => gtk::glib::Continue(true),
+            }
+        });
+    });
+
+    let window2 = Rc::clone(&window);
+    let lock_db2 = Rc::clone(&lock_db);
+    let panic_action = gtk::gio::SimpleAction::new("panic-lock", None);
+    panic_action.connect_activate(move |_, _| {
+        window2.minimize();
+        lock_db2();
+    });
+    window.add_action(&panic_action);
+    app.set_accels_for_action("win.panic-lock", &["<Control><Shift>l"]);
+
+    let window2 = Rc::clone(&window);
+    let db2 = Arc::clone(&db);
+    let lock_db2 = Rc::clone(&lock_db);
+    let lock_action2 = lock_action.clone();
+    let auto_lock: Rc<dyn Fn(&str)> = Rc::new(move |reason| {
+        if !lock_action2.is_enabled() {
+            return;
+        }
+        // Keep unsaved changes in a snapshot instead of discarding them with the lock, unless a worker holds the database.
+        if let Ok(db) = db2.try_read() {
+            if db.pending_changes() {
+                db.snapshot();
+            }
+        }
+        lock_db2();
+        notify(&window2, reason);
+    });
+    let auto_lock2 = Rc::clone(&auto_lock);
+    let session_watch = RefCell::new(watch_session(Rc::new(move || {
+        auto_lock2("locked because the session became inactive");
+    })));
+    // The window owns the connection and both subscriptions, so locking on session changes lasts as long as it does.
+    window.connect_destroy(move |_| {
+        if let Some((connection, subscriptions)) = session_watch.take() {
+            for subscription in subscriptions {
+                connection.signal_unsubscribe(subscription);
+            }
+        }
+    });
+    gtk::glib::timeout_add_seconds_local(5, move|| {
+        // Lock before the system starts swapping key material out or the OOM killer has to step in.
+        if memory_pressure().is_some_and(|stalled| stalled >= MEMORY_PRESSURE_LOCK) {
+            auto_lock("locked because the system is running out of memory");
+        }
+        gtk::glib::Continue(true)
+    });
+
+    let window2 = Rc::clone(&window);
+    let db2 = Arc::clone(&db);
+    let status_bar2 = Rc::clone(&status_bar);
+    lock_action.connect_activate(move |_, _| {
+        let pending_changes = match db2.try_read() {
+            Ok(db) => db.pending_changes(),
+            Err(_) => {
+                status_bar2.borrow().push(0, "lock unavailable while saving");
+                return;
+            }
+        };
+        if !pending_changes {
+            lock_db();
+            return;
+        }
+        let dialog = gtk::MessageDialog::builder()
+            .transient_for(&*window2)
+            .modal(true)
+            .message_type(gtk::MessageType::Question)
+            .buttons(gtk::ButtonsType::YesNo)
+            .text("Discard Unsaved Changes?")
+            .secondary_text("Locking discards changes that have not been saved. Lock anyway?")
+            .build();
+        let lock_db = Rc::clone(&lock_db);
+        dialog.connect_response(move |dialog, response| {
+            dialog.close();
+            if response == gtk::ResponseType::Yes {
+                lock_db();
+            }
+        });
+        dialog.show();
+    });
+    window.add_action(&lock_action);
+    app.set_accels_for_action("win.lock", &["<Control>l"]);
+
+    let window2 = Rc::clone(&window);
+    let db2 = Arc::clone(&db);
+    let main_box2 = Rc::clone(&main_box);
+    unlock_receiver.attach(None, move|(attempt, msg)| {
+        if attempt != unlock_attempt.load(Ordering::SeqCst) {
+            return gtk::glib::Continue(true);
+        }
+        unlock_started.set(None);
+        match remote::precedence_note() {
+            Some(note) if msg.starts_with("unlocked") => status_bar.borrow().push(0, &format!("{msg}, {note}")),
+            _ => status_bar.borrow().push(0, &msg),
+        };
+        if msg.starts_with("unlocked") {
             main_box2.borrow().set_sensitive(true);
-            gtk::glib::Continue(false)
+            for action in unlock_actions.iter() {
+                action.set_enabled(true);
+            }
+            if let Some((remote_revision, last_seen)) = db2.read().unwrap().remote_rollback() {
+                let dialog = gtk::MessageDialog::builder()
+                    .transient_for(&*window2)
+                    .modal(true)
+                    .message_type(gtk::MessageType::Warning)
+                    .buttons(gtk::ButtonsType::Close)
+                    .text("Possible Remote Rollback")
+                    .secondary_text(&format!("The remote copy is at revision {remote_revision}, but this device has already seen revision {last_seen}. The storage provider may be serving a stale or rolled back backup. Check the remote before relying on it."))
+                    .build();
+                dialog.connect_response(|dialog, _| {
+                    dialog.close();
+                });
+                dialog.show();
+            }
+            // Ask again on every unlock rather than keeping an earlier refusal.
+            let managers = clipboard_managers();
+            copy_allowed.set(managers.is_empty());
+            if !managers.is_empty() {
+                clipboard_warning(&*window2, &managers, Rc::clone(&copy_allowed));
+            }
+            let conflicts = db2.read().unwrap().conflict_count();
+            if conflicts > 0 {
+                let text = format!("Found {conflicts} Syncthing conflict copies of this database. Merge their entries and save?");
+                merge_dialog(&*window2, Arc::clone(&db2), Rc::clone(&status_bar), Rc::clone(&main_box2), "Merge Sync Conflicts", &text, appdb::AppDB::merge_conflicts);
+            }
+            let source = background_sync(Rc::clone(&window2), Arc::clone(&db2), Rc::clone(&status_bar), Rc::clone(&main_box2));
+            if let Some(old_source) = sync_source.replace(source) {
+                old_source.remove();
+            }
         } else {
-            gtk::glib::Continue(true)
+            spawn_unlock();
+        }
+        gtk::glib::Continue(true)
+    });
+
+    let db2 = Arc::clone(&db);
+    gtk::glib::timeout_add_seconds_local(5, move|| {
+        // Skip the refresh while a save or merge holds the database.
+        if let Ok(db) = db2.try_read() {
+            sync_label.set_text(&sync_status(&db));
+        }
+        gtk::glib::Continue(true)
+    });
+
+    let main_box2 = Rc::clone(&main_box);
+    let update_mask: Rc<dyn Fn()> = Rc::new(move || {
+        // Focus passes between the app's own windows in two steps, so look once both have settled.
+        let main_box = Rc::clone(&main_box2);
+        gtk::glib::idle_add_local_once(move || privacy_mask(&main_box.borrow()));
+    });
+    let update_mask2 = Rc::clone(&update_mask);
+    window.connect_is_active_notify(move |_| update_mask2());
+    // Dialogs come and go, so follow focus on every window the app opens.
+    gtk::Window::toplevels().connect_items_changed(move |toplevels, position, _, added| {
+        for idx in position..position + added {
+            if let Some(toplevel) = toplevels.item(idx).and_then(|item| item.downcast::<gtk::Window>().ok()) {
+                let update_mask = Rc::clone(&update_mask);
+                toplevel.connect_is_active_notify(move |_| update_mask());
+            }
         }
     });
 
     let window2 = Rc::clone(&window);
     gtk::glib::timeout_add_seconds_local(10, move|| { 
         window2.clipboard().set_text("");
+        if clipboard_in_use.replace(false) {
+            notify(&window2, "clipboard cleared");
+        }
         gtk::glib::Continue(true)
     });
 }
 
 
-async fn unlock_dialog<W: gtk::glib::IsA<gtk::Window>>(window: Rc<W>, db: Arc<RwLock<appdb::AppDB>>, sender: gtk::glib::Sender<String>) {
-    let db_id_entry = gtk::Entry::builder()
+const PALETTE_COMMANDS: [(&str, &str); 17] = [
+    ("Save", "win.save"),
+    ("New entry", "win.new-entry"),
+    ("Generate password", "win.generate"),
+    ("Import from pass", "win.import-pass"),
+    ("Import CSV", "win.import-csv"),
+    ("Export JSON", "win.export-json"),
+    ("Restore snapshot", "win.restore-snapshot"),
+    ("Time machine", "win.time-machine"),
+    ("Test remote restore", "win.test-restore"),
+    ("Email backup", "win.email-backup"),
+    ("Create recovery drive", "win.recovery-drive"),
+    ("Database info", "win.info"),
+    ("Settings", "win.settings"),
+    ("Export settings", "win.export-settings"),
+    ("Import settings", "win.import-settings"),
+    ("Lock", "win.lock"),
+    ("Panic lock", "win.panic-lock"),
+];
+
+
+fn fuzzy_match(query: &str, label: &str) -> bool {
+    let mut label_chars = label.chars().flat_map(char::to_lowercase);
+    query.chars().flat_map(char::to_lowercase).filter(|c| !c.is_whitespace()).all(|c| label_chars.any(|label_c| label_c == c))
+}
+
+
+fn command_palette(window: &gtk::ApplicationWindow) {
+    let search_entry = gtk::SearchEntry::builder()
         .margin_top(10)
-        .margin_bottom(10)
         .margin_start(10)
         .margin_end(10)
-        .max_length(8)
-        .tooltip_text("DB_ID")
+        .placeholder_text("Type a command")
         .build();
-    let password_entry = gtk::PasswordEntry::builder()
+    search_entry.update_property(&[gtk::accessible::Property::Label("Command")]);
+    let command_list = gtk::ListBox::builder()
         .margin_top(10)
         .margin_bottom(10)
         .margin_start(10)
         .margin_end(10)
-        .width_request(300)
-        .tooltip_text("Password")
-        .show_peek_icon(true)
         .build();
-    let unlock_button = gtk::Button::builder()
-        .label("Unlock")
+    for (label, action_name) in PALETTE_COMMANDS {
+        let enabled = window.lookup_action(&action_name["win.".len()..]).is_some_and(|action| action.is_enabled());
+        if enabled {
+            let row = gtk::ListBoxRow::builder()
+                .name(action_name)
+                .child(&gtk::Label::builder().label(label).xalign(0.0).build())
+                .build();
+            command_list.append(&row);
+        }
+    }
+    let dialog_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .build();
+    dialog_box.append(&search_entry);
+    dialog_box.append(&command_list);
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .title("Command Palette")
+        .default_width(400)
+        .modal(true)
+        .child(&dialog_box)
+        .build();
+    let search_entry2 = search_entry.clone();
+    command_list.set_filter_func(move |row| {
+        let label = row.child().and_then(|child| child.downcast::<gtk::Label>().ok()).map(|label| label.text().to_string()).unwrap_or_default();
+        fuzzy_match(&search_entry2.text(), &label)
+    });
+    let command_list2 = command_list.clone();
+    search_entry.connect_search_changed(move |_| {
+        command_list2.invalidate_filter();
+        if let Some(row) = first_visible_row(&command_list2) {
+            command_list2.select_row(Some(&row));
+        }
+    });
+    let window2 = window.clone();
+    let dialog2 = dialog.clone();
+    command_list.connect_row_activated(move |_, row| {
+        dialog2.close();
+        let _ = WidgetExt::activate_action(&window2, &row.widget_name(), None);
+    });
+    let command_list2 = command_list.clone();
+    search_entry.connect_activate(move |_| {
+        if let Some(row) = command_list2.selected_row().or_else(|| first_visible_row(&command_list2)) {
+            row.activate();
+        }
+    });
+    let dialog2 = dialog.clone();
+    search_entry.connect_stop_search(move |_| {
+        dialog2.close();
+    });
+    dialog.show();
+}
+
+
+fn first_visible_row(command_list: &gtk::ListBox) -> Option<gtk::ListBoxRow> {
+    let mut idx = 0;
+    while let Some(row) = command_list.row_at_index(idx) {
+        if row.is_child_visible() {
+            return Some(row);
+        }
+        idx += 1;
+    }
+    None
+}
+
+
+const CLIPBOARD_MANAGERS: [&str; 13] = [
+    "cliphist", "clipcat", "clipit", "clipman", "clipse", "copyq", "diodon", "gpaste-daemon",
+    "greenclip", "klipper", "parcellite", "wl-clip-persist", "xfce4-clipman",
+];
+
+
+fn clipboard_managers() -> Vec<String> {
+    let mut managers: Vec<String> = std::fs::read_dir("/proc").into_iter().flatten().flatten()
+        .filter_map(|proc_entry| std::fs::read_to_string(proc_entry.path().join("comm")).ok())
+        .map(|comm| comm.trim().to_owned())
+        .filter(|comm| CLIPBOARD_MANAGERS.contains(&comm.as_str()))
+        .collect();
+    managers.sort();
+    managers.dedup();
+    managers
+}
+
+
+fn clipboard_warning<W: gtk::glib::IsA<gtk::Window>>(window: &W, managers: &[String], copy_allowed: Rc<Cell<bool>>) {
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Warning)
+        .buttons(gtk::ButtonsType::OkCancel)
+        .text("Clipboard Manager Running")
+        .secondary_text(&format!("{} may keep copied passwords in its history even after DigiSafe clears the clipboard. Allow copying anyway?", managers.join(", ")))
+        .build();
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        copy_allowed.set(response == gtk::ResponseType::Ok);
+    });
+    dialog.show();
+}
+
+
+fn format_time(time: Option<std::time::SystemTime>, format: &str) -> String {
+    time.and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .and_then(|since_epoch| gtk::glib::DateTime::from_unix_local(since_epoch.as_secs() as i64).ok())
+        .and_then(|date_time| date_time.format(format).ok())
+        .map_or("never".to_owned(), |formatted| formatted.to_string())
+}
+
+
+fn key_changed_dialog(set_button: &gtk::Button, db: Arc<RwLock<appdb::AppDB>>, status_bar: Rc<RefCell<gtk::Statusbar>>, editing_key: Rc<RefCell<Option<String>>>, val_entry: Rc<RefCell<gtk::TextView>>, old_key: String, key: String) {
+    let dialog = gtk::MessageDialog::builder()
+        .modal(true)
+        .message_type(gtk::MessageType::Question)
+        .buttons(gtk::ButtonsType::Cancel)
+        .text("Key Changed")
+        .secondary_text("The key was edited after this entry was loaded. Rename the entry, keep both under a duplicate, or start a new entry under this key?")
+        .build();
+    if let Some(window) = set_button.root().and_then(|root| root.downcast::<gtk::Window>().ok()) {
+        dialog.set_transient_for(Some(&window));
+    }
+    dialog.add_button("New Entry", gtk::ResponseType::Other(3));
+    dialog.add_button("Duplicate", gtk::ResponseType::Other(2));
+    dialog.add_button("Rename", gtk::ResponseType::Other(1));
+    let set_button = set_button.clone();
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        let bounds = val_entry.borrow().buffer().bounds();
+        let val = appdb::SecretString::new(val_entry.borrow().buffer().text(&bounds.0, &bounds.1, false).to_string());
+        let msg = match response {
+            gtk::ResponseType::Other(1) => {
+                let mut db = db.write().unwrap();
+                if !db.rename(&old_key, key.clone()) {
+                    status_bar.borrow().push(0, "entry not renamed, key empty or entry gone");
+                    return;
+                }
+                // An emptied value keeps the stored one rather than deleting the renamed entry.
+                if !val.is_empty() {
+                    db.set(key.clone(), val);
+                }
+                "entry renamed"
+            },
+            gtk::ResponseType::Other(2) => {
+                db.write().unwrap().set(key.clone(), val);
+                "entry duplicated"
+            },
+            gtk::ResponseType::Other(3) => {
+                val_entry.borrow().buffer().set_text("");
+                set_entry_mode(&set_button, &editing_key, None);
+                WidgetExt::activate_action(&set_button, "win.generate", None).ok();
+                return;
+            },
+            _ => return,
+        };
+        status_bar.borrow().push(0, msg);
+        set_entry_mode(&set_button, &editing_key, Some(key.clone()));
+    });
+    dialog.show();
+}
+
+fn update_dialog(set_button: &gtk::Button, db: Arc<RwLock<appdb::AppDB>>, status_bar: Rc<RefCell<gtk::Statusbar>>, editing_key: Rc<RefCell<Option<String>>>, key: String, val: appdb::SecretString, diff: &str) {
+    let dialog = gtk::MessageDialog::builder()
+        .modal(true)
+        .message_type(gtk::MessageType::Question)
+        .buttons(gtk::ButtonsType::YesNo)
+        .text("Update Entry?")
+        .secondary_text(diff)
+        .build();
+    if let Some(window) = set_button.root().and_then(|root| root.downcast::<gtk::Window>().ok()) {
+        dialog.set_transient_for(Some(&window));
+    }
+    let set_button = set_button.clone();
+    let val = RefCell::new(Some(val));
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        if response != gtk::ResponseType::Yes {
+            return;
+        }
+        if let Some(val) = val.take() {
+            db.write().unwrap().set(key.clone(), val);
+            status_bar.borrow().push(0, "entry updated");
+            set_entry_mode(&set_button, &editing_key, Some(key.clone()));
+        }
+    });
+    dialog.show();
+}
+
+// Fields follow the pass layout: the first line is the password, later lines are usually "label: value".
+fn entry_diff(old_val: &str, new_val: &str) -> String {
+    const SECRET_LABELS: [&str; 6] = ["pass", "pin", "secret", "token", "key", "otp"];
+    let old_lines: Vec<&str> = old_val.lines().collect();
+    let new_lines: Vec<&str> = new_val.lines().collect();
+    let mut diff = Vec::new();
+    for line in 0..old_lines.len().max(new_lines.len()) {
+        let (old_line, new_line) = (old_lines.get(line).copied(), new_lines.get(line).copied());
+        if old_line == new_line {
+            continue;
+        }
+        let (label, secret) = match new_line.or(old_line).and_then(|field| field.split_once(':')) {
+            _ if line == 0 => ("password".to_owned(), true),
+            Some((label, _)) => (label.trim().to_owned(), SECRET_LABELS.iter().any(|secret| label.to_lowercase().contains(secret))),
+            None => (format!("line {}", line + 1), false),
+        };
+        let show = |field: Option<&str>| match field {
+            None => "(none)".to_owned(),
+            Some(_) if secret => "••••••".to_owned(),
+            Some(field) => field.split_once(':').filter(|(field_label, _)| field_label.trim() == label).map_or(field, |(_, value)| value.trim()).to_owned(),
+        };
+        diff.push(format!("{label}: {} → {}", show(old_line), show(new_line)));
+    }
+    diff.join("\n")
+}
+
+fn set_entry_mode(set_button: &gtk::Button, editing_key: &RefCell<Option<String>>, key: Option<String>) {
+    set_button.set_label(if key.is_some() { "Update" } else { "Add" });
+    *editing_key.borrow_mut() = key;
+}
+
+fn sync_status(db: &appdb::AppDB) -> String {
+    let pending = if db.pending_changes() { "unsaved changes" } else { "no unsaved changes" };
+    format!("{} · {} entries · saved {} · synced {} · {}", db.db_id(), db.entry_count(), format_time(db.last_save(), "%H:%M"), format_time(db.last_sync(), "%H:%M"), pending)
+}
+
+
+fn info_dialog<W: gtk::glib::IsA<gtk::Window>>(window: &W, db: Arc<RwLock<appdb::AppDB>>, status_bar: Rc<RefCell<gtk::Statusbar>>) {
+    let db_read = db.read().unwrap();
+    let info = format!(
+        "Database ID: {}\nRevision: {}\nEntries: {}\nEncrypted size: {} bytes\nCreated: {}\nModified: {}",
+        db_read.db_id(),
+        db_read.revision(),
+        db_read.entry_count(),
+        db_read.approximate_size(),
+        format_time(db_read.created_ts(), "%Y-%m-%d %H:%M"),
+        format_time(db_read.modified_ts(), "%Y-%m-%d %H:%M"),
+    );
+    let sync_direction_combo = gtk::ComboBoxText::builder()
+        .tooltip_text("Sync Direction")
+        .build();
+    sync_direction_combo.append(Some("both"), "Sync both ways");
+    sync_direction_combo.append(Some("push"), "Push only (this machine is the source of truth)");
+    sync_direction_combo.append(Some("pull"), "Pull only (read replica, saving disabled)");
+    sync_direction_combo.set_active_id(Some(&db_read.sync_direction()));
+    sync_direction_combo.update_property(&[gtk::accessible::Property::Label("Sync direction")]);
+    drop(db_read);
+    sync_direction_combo.connect_changed(move |sync_direction_combo| {
+        if let Some(direction) = sync_direction_combo.active_id() {
+            if let Err(err) = db.read().unwrap().set_sync_direction(&direction) {
+                status_bar.borrow().push(0, &format!("sync direction not saved: {err}"));
+            }
+        }
+    });
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Info)
+        .buttons(gtk::ButtonsType::Close)
+        .text("Database Info")
+        .secondary_text(&info)
+        .build();
+    if let Ok(message_area) = dialog.message_area().downcast::<gtk::Box>() {
+        message_area.append(&sync_direction_combo);
+    }
+    dialog.connect_response(|dialog, _| {
+        dialog.close();
+    });
+    dialog.show();
+}
+
+
+async fn unlock_dialog<W: gtk::glib::IsA<gtk::Window>>(window: Rc<W>, db: Arc<RwLock<appdb::AppDB>>, sender: gtk::glib::Sender<(u32, String)>, attempt: Arc<AtomicU32>, started: Rc<Cell<Option<Instant>>>) {
+    let db_id_entry = gtk::Entry::builder()
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .max_length(8)
+        .tooltip_text("DB_ID")
+        .build();
+    let password_entry = gtk::PasswordEntry::builder()
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .width_request(300)
+        .tooltip_text("Password")
+        .show_peek_icon(true)
+        .build();
+    let split_check = gtk::CheckButton::builder()
+        .label("Two-person unlock")
+        .tooltip_text("The database key is derived from two passwords held by different people")
+        .margin_start(10)
+        .margin_end(10)
+        .build();
+    let second_password_entry = gtk::PasswordEntry::builder()
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .width_request(300)
+        .tooltip_text("Second Password")
+        .show_peek_icon(true)
+        .visible(false)
+        .build();
+    let unlock_button = gtk::Button::builder()
+        .label("Unlock")
         .margin_top(10)
         .margin_bottom(10)
         .margin_start(20)
         .margin_end(20)
         .build();
+    db_id_entry.update_property(&[gtk::accessible::Property::Label("Database ID")]);
+    password_entry.update_property(&[gtk::accessible::Property::Label("Password")]);
+    second_password_entry.update_property(&[gtk::accessible::Property::Label("Second password")]);
+    let second_password_entry2 = second_password_entry.clone();
+    split_check.connect_toggled(move |split_check| {
+        second_password_entry2.set_visible(split_check.is_active());
+        second_password_entry2.set_text("");
+    });
+    let password_entry2 = password_entry.clone();
+    db_id_entry.connect_activate(move |_| {
+        password_entry2.grab_focus();
+    });
+    let unlock_button2 = unlock_button.clone();
+    let second_password_entry2 = second_password_entry.clone();
+    password_entry.connect_activate(move |_| {
+        if second_password_entry2.is_visible() {
+            second_password_entry2.grab_focus();
+        } else {
+            unlock_button2.emit_clicked();
+        }
+    });
+    let unlock_button2 = unlock_button.clone();
+    second_password_entry.connect_activate(move |_| {
+        unlock_button2.emit_clicked();
+    });
     let dialog_box = gtk::Box::builder()
         .orientation(gtk::Orientation::Vertical)
         .halign(gtk::Align::Center)
         .build();
+    let keyboard = on_screen_keyboard(&password_entry);
+    keyboard.set_visible(false);
+    let keyboard_button = gtk::ToggleButton::builder()
+        .label("On-screen keyboard")
+        .tooltip_text("Enter the password by clicking shuffled keys")
+        .margin_start(20)
+        .margin_end(20)
+        .build();
+    let keyboard2 = keyboard.clone();
+    keyboard_button.connect_toggled(move |keyboard_button| {
+        keyboard2.set_visible(keyboard_button.is_active());
+    });
     dialog_box.append(&db_id_entry);
     dialog_box.append(&password_entry);
+    dialog_box.append(&split_check);
+    dialog_box.append(&second_password_entry);
+    dialog_box.append(&keyboard_button);
+    dialog_box.append(&keyboard);
     dialog_box.append(&unlock_button);
     let dialog = Rc::new(gtk::Dialog::builder()
         .transient_for(&*window)
@@ -206,17 +1329,900 @@ async fn unlock_dialog<W: gtk::glib::IsA<gtk::Window>>(window: Rc<W>, db: Arc<Rw
     let dbc = Arc::clone(&db);
     unlock_button.connect_clicked(move |_| {
         let raw_db_id = db_id_entry.text().to_string();
-        let raw_password = password_entry.text().to_string();
+        let raw_password = appdb::SecretString::new(password_entry.text().to_string());
+        password_entry.set_text("");
+        let raw_second_password = appdb::SecretString::new(second_password_entry.text().to_string());
+        second_password_entry.set_text("");
         let dbcc = Arc::clone(&dbc);
         let sender = sender.clone();
+        let attempt = Arc::clone(&attempt);
+        let attempt_id = attempt.fetch_add(1, Ordering::SeqCst) + 1;
+        started.set(Some(Instant::now()));
         std::thread::spawn(move || {
-            dbcc.write().unwrap().set_db_id(raw_db_id);
-            dbcc.write().unwrap().set_password(raw_password);
-            let msg = dbcc.write().unwrap().load();
-            sender.send(msg).expect("unlock failure");
+            let (version, password) = appdb::AppDB::discover_key(&raw_db_id, &raw_password, &raw_second_password);
+            let mut db = dbcc.write().unwrap();
+            if attempt.load(Ordering::SeqCst) != attempt_id {
+                return;
+            }
+            db.set_db_id(raw_db_id);
+            db.set_version(version);
+            db.set_password_hash(&password);
+            db.set_split_unlock(!raw_second_password.is_empty());
+            let msg = db.load();
+            sender.send((attempt_id, msg)).expect("unlock failure");
         });
         dialog_clone.close();
         });
     dialog.run_future().await;
 }
 
+
+const KEYBOARD_CHARS: &str = "abcdefghijklmnopqrstuvwxyz0123456789`~!@#$%^&*()-_=+[]{}\\|;:'\",.<>/?";
+
+
+const UNLOCK_WATCHDOG_SECS: u64 = 45;
+
+fn slow_unlock_dialog<W: gtk::glib::IsA<gtk::Window>>(window: &W, cancel_unlock_button: gtk::Button) {
+    // MemAvailable is the kernel's estimate of memory usable without swapping.
+    let available_mib = std::fs::read_to_string("/proc/meminfo").ok().and_then(|meminfo| {
+        let line = meminfo.lines().find(|line| line.starts_with("MemAvailable:"))?;
+        line.split_whitespace().nth(1)?.parse::<u64>().ok().map(|kib| kib / 1024)
+    });
+    let memory_note = available_mib.map(|mib| format!(" About {mib} MiB is currently available.")).unwrap_or_default();
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Warning)
+        .buttons(gtk::ButtonsType::YesNo)
+        .text("Unlock Is Taking Unusually Long")
+        .secondary_text(&format!("Key derivation needs about 1 GiB of memory and has run for over {UNLOCK_WATCHDOG_SECS} seconds, so the system may be swapping.{memory_note} Closing other applications frees memory and lets it finish sooner. A running derivation cannot be stopped: cancelling only lets you re-enter the password, and the next attempt waits until this one ends. Cancel this unlock?"))
+        .build();
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        if response == gtk::ResponseType::Yes && cancel_unlock_button.is_visible() {
+            cancel_unlock_button.emit_clicked();
+        }
+    });
+    dialog.show();
+}
+
+fn on_screen_keyboard(password_entry: &gtk::PasswordEntry) -> gtk::Box {
+    let key_grid = gtk::Grid::builder()
+        .row_homogeneous(true)
+        .column_homogeneous(true)
+        .build();
+    let keys: Rc<Vec<gtk::Button>> = Rc::new(KEYBOARD_CHARS.chars().map(|_| gtk::Button::new()).collect());
+    let shift_button = gtk::ToggleButton::builder()
+        .label("Shift")
+        .build();
+    let keys2 = Rc::clone(&keys);
+    let shift_button2 = shift_button.clone();
+    // Reshuffle after every key so click positions reveal nothing about the password.
+    let shuffle_keys = Rc::new(move || {
+        let mut chars: Vec<char> = KEYBOARD_CHARS.chars().collect();
+        generator::shuffle(&mut chars);
+        for (key, c) in keys2.iter().zip(chars) {
+            let c = if shift_button2.is_active() { c.to_ascii_uppercase() } else { c };
+            key.set_label(&c.to_string());
+        }
+    });
+    for (idx, key) in keys.iter().enumerate() {
+        key_grid.attach(key, (idx % 12) as i32, (idx / 12) as i32, 1, 1);
+        let password_entry = password_entry.clone();
+        let shuffle_keys = Rc::clone(&shuffle_keys);
+        key.connect_clicked(move |key| {
+            if let Some(label) = key.label() {
+                let mut position = password_entry.position();
+                password_entry.insert_text(&label, &mut position);
+                password_entry.set_position(position);
+            }
+            shuffle_keys();
+        });
+    }
+    shuffle_keys();
+    let shuffle_keys2 = Rc::clone(&shuffle_keys);
+    shift_button.connect_toggled(move |_| {
+        shuffle_keys2();
+    });
+    let space_button = gtk::Button::builder()
+        .label("Space")
+        .build();
+    let password_entry2 = password_entry.clone();
+    space_button.connect_clicked(move |_| {
+        let mut position = password_entry2.position();
+        password_entry2.insert_text(" ", &mut position);
+        password_entry2.set_position(position);
+    });
+    let backspace_button = gtk::Button::builder()
+        .label("Backspace")
+        .build();
+    let password_entry2 = password_entry.clone();
+    backspace_button.connect_clicked(move |_| {
+        let position = password_entry2.position();
+        if position > 0 {
+            password_entry2.delete_text(position - 1, position);
+        }
+    });
+    let control_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .halign(gtk::Align::Center)
+        .spacing(10)
+        .build();
+    control_box.append(&shift_button);
+    control_box.append(&space_button);
+    control_box.append(&backspace_button);
+    let keyboard = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .spacing(10)
+        .build();
+    keyboard.append(&key_grid);
+    keyboard.append(&control_box);
+    keyboard
+}
+
+
+fn watch_session(lock: Rc<dyn Fn()>) -> Option<(gtk::gio::DBusConnection, [gtk::gio::SignalSubscriptionId; 2])> {
+    let connection = gtk::gio::bus_get_sync(gtk::gio::BusType::System, None::<&gtk::gio::Cancellable>).ok()?;
+    let session = connection.call_sync(
+        Some("org.freedesktop.login1"),
+        "/org/freedesktop/login1",
+        "org.freedesktop.login1.Manager",
+        "GetSessionByPID",
+        Some(&(std::process::id(),).to_variant()),
+        Some(gtk::glib::VariantTy::new("(o)").unwrap()),
+        gtk::gio::DBusCallFlags::NONE,
+        -1,
+        None::<&gtk::gio::Cancellable>,
+    );
+    let session_path = session.ok().and_then(|session| session.child_value(0).str().map(str::to_owned))?;
+    let lock2 = Rc::clone(&lock);
+    let deactivated = connection.signal_subscribe(Some("org.freedesktop.login1"), Some("org.freedesktop.DBus.Properties"), Some("PropertiesChanged"), Some(&session_path), None, gtk::gio::DBusSignalFlags::NONE, move |_, _, _, _, _, params| {
+        // Active turns false when another user or VT takes the seat.
+        let changed = gtk::glib::VariantDict::new(Some(&params.child_value(1)));
+        if let Ok(Some(false)) = changed.lookup::<bool>("Active") {
+            lock2();
+        }
+    });
+    let removed = connection.signal_subscribe(Some("org.freedesktop.login1"), Some("org.freedesktop.login1.Manager"), Some("SessionRemoved"), Some("/org/freedesktop/login1"), None, gtk::gio::DBusSignalFlags::NONE, move |_, _, _, _, _, params| {
+        if params.child_value(1).str() == Some(session_path.as_str()) {
+            lock();
+        }
+    });
+    Some((connection, [deactivated, removed]))
+}
+
+
+// Masking only hides the form while another application has focus; the database stays unlocked, so nothing needs re-entering.
+fn privacy_mask(main_box: &gtk::Box) {
+    let toplevels = gtk::Window::toplevels();
+    let app_focused = (0..toplevels.n_items())
+        .filter_map(|idx| toplevels.item(idx).and_then(|item| item.downcast::<gtk::Window>().ok()))
+        .any(|toplevel| toplevel.is_active());
+    let masked = !app_focused && settings::get("privacy_mask").is_some();
+    main_box.set_opacity(if masked { 0.0 } else { 1.0 });
+    // A transparent form still takes focus and clicks and is read out, so withdraw it from all three.
+    main_box.set_can_focus(!masked);
+    main_box.set_can_target(!masked);
+    main_box.update_state(&[gtk::accessible::State::Hidden(masked)]);
+}
+
+
+// Percentage of the last 10 seconds in which every task was stalled waiting on memory.
+const MEMORY_PRESSURE_LOCK: f64 = 10.0;
+
+fn memory_pressure() -> Option<f64> {
+    let pressure = std::fs::read_to_string("/proc/pressure/memory").ok()?;
+    let full = pressure.lines().find(|line| line.starts_with("full "))?;
+    full.split_whitespace().find_map(|field| field.strip_prefix("avg10="))?.parse::<f64>().ok()
+}
+
+
+fn notify(window: &gtk::ApplicationWindow, body: &str) {
+    // Only notify while the window is unfocused; notifications never carry entry names or values.
+    if window.is_active() {
+        return;
+    }
+    if let Some(app) = window.application() {
+        let notification = gtk::gio::Notification::new("DigiSafe");
+        notification.set_body(Some(body));
+        app.send_notification(Some("digisafe"), &notification);
+    }
+}
+
+
+fn background_sync(window: Rc<gtk::ApplicationWindow>, db: Arc<RwLock<appdb::AppDB>>, status_bar: Rc<RefCell<gtk::Statusbar>>, main_box: Rc<RefCell<gtk::Box>>) -> Option<gtk::glib::SourceId> {
+    let sync_minutes = settings::get("sync_minutes").and_then(|minutes| minutes.parse::<u32>().ok()).unwrap_or(0);
+    if sync_minutes == 0 {
+        return None;
+    }
+    let (sync_sender, sync_receiver) = gtk::glib::MainContext::channel::<Result<String, String>>(gtk::glib::PRIORITY_DEFAULT);
+    let db2 = Arc::clone(&db);
+    let offered_revision = RefCell::new(String::new());
+    sync_receiver.attach(None, move|newer_revision| {
+        let revision = match newer_revision {
+            Ok(revision) => revision,
+            Err(_) => {
+                status_bar.borrow().push(0, "sync failure");
+                notify(&window, "remote sync failed");
+                return gtk::glib::Continue(true);
+            }
+        };
+        if *offered_revision.borrow() != revision {
+            offered_revision.replace(revision.clone());
+            let text = format!("The remote copy has newer revision {revision}. Merge it and save?");
+            merge_dialog(&*window, Arc::clone(&db2), Rc::clone(&status_bar), Rc::clone(&main_box), "Merge Remote Changes", &text, appdb::AppDB::merge_remote);
+        }
+        gtk::glib::Continue(true)
+    });
+    let source = gtk::glib::timeout_add_seconds_local(sync_minutes * 60, move|| {
+        // Skip this round while a save or merge holds the database; push-only databases never pull.
+        if let Some(db) = db.try_read().ok().filter(|db| db.sync_direction() != "push") {
+            let remote_id = db.remote_id();
+            let revision = db.revision();
+            let sync_sender = sync_sender.clone();
+            std::thread::spawn(move || {
+                match appdb::AppDB::newer_remote_revision(&remote_id, &revision) {
+                    Ok(Some(remote_revision)) => sync_sender.send(Ok(remote_revision)).expect("sync sender error"),
+                    Ok(None) => {}
+                    Err(err) => sync_sender.send(Err(err)).expect("sync sender error"),
+                }
+            });
+        }
+        gtk::glib::Continue(true)
+    });
+    Some(source)
+}
+
+
+fn time_machine_dialog<W: gtk::glib::IsA<gtk::Window>>(window: &W, db: Arc<RwLock<appdb::AppDB>>, status_bar: Rc<RefCell<gtk::Statusbar>>) {
+    // A missing path stands for the current remote copy, which is only fetched on demand.
+    let mut revisions: Vec<(String, Option<std::path::PathBuf>)> = Vec::new();
+    if remote::is_configured() {
+        revisions.push(("remote · current".to_owned(), None));
+    }
+    for (source, path, modified) in db.read().unwrap().revision_files() {
+        revisions.push((format!("{source} · {}", format_time(modified, "%Y-%m-%d %H:%M")), Some(path)));
+    }
+    let revision_list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .min_content_height(400)
+        .child(&revision_list)
+        .build();
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .title("Time Machine")
+        .default_width(600)
+        .modal(true)
+        .child(&scrolled_window)
+        .build();
+    let (inspect_sender, inspect_receiver) = gtk::glib::MainContext::channel::<(usize, Result<(String, usize), String>)>(gtk::glib::PRIORITY_DEFAULT);
+    let (restore_sender, restore_receiver) = gtk::glib::MainContext::channel::<String>(gtk::glib::PRIORITY_DEFAULT);
+    type Compared = Result<Vec<(String, &'static str)>, String>;
+    let (pick_sender, pick_receiver) = gtk::glib::MainContext::channel::<(Option<std::path::PathBuf>, Compared)>(gtk::glib::PRIORITY_DEFAULT);
+    let mut detail_labels = Vec::new();
+    for (idx, (title, path)) in revisions.into_iter().enumerate() {
+        let title_label = gtk::Label::builder()
+            .label(&title)
+            .xalign(0.0)
+            .hexpand(true)
+            .build();
+        let detail_label = gtk::Label::builder()
+            .label("not inspected")
+            .margin_start(10)
+            .margin_end(10)
+            .build();
+        let inspect_button = gtk::Button::builder()
+            .label("Inspect")
+            .tooltip_text("Decrypt this revision and count its entries")
+            .build();
+        let pick_button = gtk::Button::builder()
+            .label("Pick Entries…")
+            .tooltip_text("Compare with the live entries and restore selected ones")
+            .margin_start(10)
+            .build();
+        let restore_button = gtk::Button::builder()
+            .label("Restore")
+            .tooltip_text("Replace all entries with this revision")
+            .margin_start(10)
+            .build();
+        let row_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .margin_top(5)
+            .margin_bottom(5)
+            .margin_start(10)
+            .margin_end(10)
+            .build();
+        row_box.append(&title_label);
+        row_box.append(&detail_label);
+        row_box.append(&inspect_button);
+        row_box.append(&pick_button);
+        row_box.append(&restore_button);
+        revision_list.append(&row_box);
+        let db2 = Arc::clone(&db);
+        let path2 = path.clone();
+        let detail_label2 = detail_label.clone();
+        let inspect_sender = inspect_sender.clone();
+        inspect_button.connect_clicked(move |_| {
+            detail_label2.set_text("decrypting");
+            let db = Arc::clone(&db2);
+            let path = path2.clone();
+            let inspect_sender = inspect_sender.clone();
+            std::thread::spawn(move || {
+                let db = db.read().unwrap();
+                let inspected = db.read_revision(path.as_deref()).and_then(|rdb| db.inspect_revision(&rdb));
+                inspect_sender.send((idx, inspected)).expect("inspect sender error");
+            });
+        });
+        let db2 = Arc::clone(&db);
+        let path2 = path.clone();
+        let pick_sender = pick_sender.clone();
+        pick_button.connect_clicked(move |_| {
+            let db = Arc::clone(&db2);
+            let path = path2.clone();
+            let pick_sender = pick_sender.clone();
+            std::thread::spawn(move || {
+                let rdb = db.read().unwrap().read_revision(path.as_deref());
+                let compared = rdb.and_then(|rdb| db.write().unwrap().compare_revision(&rdb));
+                pick_sender.send((path, compared)).expect("pick sender error");
+            });
+        });
+        let db2 = Arc::clone(&db);
+        let restore_sender = restore_sender.clone();
+        restore_button.connect_clicked(move |_| {
+            let db = Arc::clone(&db2);
+            let path = path.clone();
+            let restore_sender = restore_sender.clone();
+            std::thread::spawn(move || {
+                let rdb = db.read().unwrap().read_revision(path.as_deref());
+                let msg = match rdb {
+                    Ok(rdb) => db.write().unwrap().restore_revision(&rdb),
+                    Err(err) => format!("restore failure: {err}"),
+                };
+                restore_sender.send(msg).expect("restore sender error");
+            });
+        });
+        detail_labels.push(detail_label);
+    }
+    if detail_labels.is_empty() {
+        revision_list.append(&gtk::Label::builder().label("No revisions found").margin_top(20).build());
+    }
+    inspect_receiver.attach(None, move|(idx, inspected)| {
+        let detail = match inspected {
+            Ok((revision, entry_count)) => format!("revision {revision} · {entry_count} entries"),
+            Err(err) => err,
+        };
+        detail_labels[idx].set_text(&detail);
+        gtk::glib::Continue(true)
+    });
+    let dialog2 = dialog.clone();
+    let status_bar2 = Rc::clone(&status_bar);
+    restore_receiver.attach(None, move|msg| {
+        status_bar2.borrow().push(0, &msg);
+        if msg.starts_with("restored") {
+            dialog2.close();
+        }
+        gtk::glib::Continue(true)
+    });
+    let dialog2 = dialog.clone();
+    pick_receiver.attach(None, move|(path, compared)| {
+        match compared {
+            Ok(compared) => entry_picker_dialog(&dialog2, Arc::clone(&db), Rc::clone(&status_bar), path, compared),
+            Err(err) => {
+                status_bar.borrow().push(0, &format!("restore failure: {err}"));
+            }
+        }
+        gtk::glib::Continue(true)
+    });
+    dialog.show();
+}
+
+
+fn entry_picker_dialog<W: gtk::glib::IsA<gtk::Window>>(window: &W, db: Arc<RwLock<appdb::AppDB>>, status_bar: Rc<RefCell<gtk::Statusbar>>, path: Option<std::path::PathBuf>, compared: Vec<(String, &'static str)>) {
+    let entry_list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    let mut picks = Vec::new();
+    for (key, status) in compared {
+        // Entries added since the revision have nothing to restore, but are listed for the comparison.
+        let check_button = gtk::CheckButton::builder()
+            .label(&key)
+            .sensitive(status != "added since" && status != "unchanged")
+            .hexpand(true)
+            .build();
+        let row_box = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .margin_top(5)
+            .margin_bottom(5)
+            .margin_start(10)
+            .margin_end(10)
+            .build();
+        row_box.append(&check_button);
+        row_box.append(&gtk::Label::new(Some(status)));
+        entry_list.append(&row_box);
+        picks.push((key, check_button));
+    }
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .margin_top(10)
+        .margin_start(10)
+        .margin_end(10)
+        .min_content_height(400)
+        .vexpand(true)
+        .child(&entry_list)
+        .build();
+    let restore_button = gtk::Button::builder()
+        .label("Restore Selected")
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(20)
+        .margin_end(20)
+        .build();
+    let dialog_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .build();
+    dialog_box.append(&scrolled_window);
+    dialog_box.append(&restore_button);
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .title("Pick Entries")
+        .default_width(500)
+        .modal(true)
+        .child(&dialog_box)
+        .build();
+    let (restore_sender, restore_receiver) = gtk::glib::MainContext::channel::<String>(gtk::glib::PRIORITY_DEFAULT);
+    let dialog2 = dialog.clone();
+    restore_receiver.attach(None, move|msg| {
+        status_bar.borrow().push(0, &msg);
+        dialog2.close();
+        gtk::glib::Continue(false)
+    });
+    restore_button.connect_clicked(move |restore_button| {
+        let keys: Vec<String> = picks.iter().filter(|(_, check_button)| check_button.is_active()).map(|(key, _)| key.to_owned()).collect();
+        if keys.is_empty() {
+            return;
+        }
+        restore_button.set_sensitive(false);
+        let db = Arc::clone(&db);
+        let path = path.clone();
+        let restore_sender = restore_sender.clone();
+        std::thread::spawn(move || {
+            let rdb = db.read().unwrap().read_revision(path.as_deref());
+            let msg = match rdb {
+                Ok(rdb) => db.write().unwrap().restore_entries(&rdb, &keys),
+                Err(err) => format!("restore failure: {err}"),
+            };
+            restore_sender.send(msg).expect("restore sender error");
+        });
+    });
+    dialog.show();
+}
+
+
+fn merge_dialog<W: gtk::glib::IsA<gtk::Window>>(window: &W, db: Arc<RwLock<appdb::AppDB>>, status_bar: Rc<RefCell<gtk::Statusbar>>, main_box: Rc<RefCell<gtk::Box>>, title: &str, text: &str, merge: fn(&mut appdb::AppDB) -> String) {
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Question)
+        .buttons(gtk::ButtonsType::YesNo)
+        .text(title)
+        .secondary_text(text)
+        .build();
+    let (merge_sender, merge_receiver) = gtk::glib::MainContext::channel::<String>(gtk::glib::PRIORITY_DEFAULT);
+    let main_box2 = Rc::clone(&main_box);
+    merge_receiver.attach(None, move|msg| {
+        status_bar.borrow().push(0, &msg);
+        main_box2.borrow().set_sensitive(true);
+        gtk::glib::Continue(false)
+    });
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        if response == gtk::ResponseType::Yes {
+            main_box.borrow().set_sensitive(false);
+            let db = Arc::clone(&db);
+            let merge_sender = merge_sender.clone();
+            std::thread::spawn(move || {
+                let msg = merge(&mut db.write().unwrap());
+                merge_sender.send(msg).expect("merge sender error");
+            });
+        }
+    });
+    dialog.show();
+}
+
+
+// Calls then with the passwords once they are verified against the open database; the second is empty unless it was opened by two people.
+fn reauth_dialog<W: gtk::glib::IsA<gtk::Window>>(window: &W, db: Arc<RwLock<appdb::AppDB>>, status_bar: Rc<RefCell<gtk::Statusbar>>, then: Rc<dyn Fn(appdb::SecretString, appdb::SecretString)>) {
+    let password_entry = gtk::PasswordEntry::builder()
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .width_request(300)
+        .tooltip_text("Password")
+        .show_peek_icon(true)
+        .build();
+    let second_password_entry = gtk::PasswordEntry::builder()
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .width_request(300)
+        .tooltip_text("Second Password")
+        .show_peek_icon(true)
+        .visible(db.read().unwrap().split_unlock())
+        .build();
+    password_entry.update_property(&[gtk::accessible::Property::Label("Password")]);
+    second_password_entry.update_property(&[gtk::accessible::Property::Label("Second password")]);
+    let confirm_button = gtk::Button::builder()
+        .label("Confirm")
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(20)
+        .margin_end(20)
+        .build();
+    let confirm_button2 = confirm_button.clone();
+    let second_password_entry2 = second_password_entry.clone();
+    password_entry.connect_activate(move |_| {
+        if second_password_entry2.is_visible() {
+            second_password_entry2.grab_focus();
+        } else {
+            confirm_button2.emit_clicked();
+        }
+    });
+    let confirm_button2 = confirm_button.clone();
+    second_password_entry.connect_activate(move |_| {
+        confirm_button2.emit_clicked();
+    });
+    let dialog_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .halign(gtk::Align::Center)
+        .build();
+    dialog_box.append(&password_entry);
+    dialog_box.append(&second_password_entry);
+    dialog_box.append(&confirm_button);
+    let dialog = gtk::Dialog::builder()
+        .transient_for(window)
+        .title("Confirm Password")
+        .default_width(300)
+        .modal(true)
+        .child(&dialog_box)
+        .build();
+    let (reauth_sender, reauth_receiver) = gtk::glib::MainContext::channel::<Option<(appdb::SecretString, appdb::SecretString)>>(gtk::glib::PRIORITY_DEFAULT);
+    let dialog2 = dialog.clone();
+    reauth_receiver.attach(None, move|verified| {
+        dialog2.close();
+        if let Some((raw_password, raw_second_password)) = verified {
+            then(raw_password, raw_second_password);
+        } else {
+            status_bar.borrow().push(0, "re-authentication failed");
+        }
+        gtk::glib::Continue(false)
+    });
+    confirm_button.connect_clicked(move |confirm_button| {
+        confirm_button.set_sensitive(false);
+        confirm_button.set_label("Verifying…");
+        let raw_password = appdb::SecretString::new(password_entry.text().to_string());
+        password_entry.set_text("");
+        let raw_second_password = appdb::SecretString::new(second_password_entry.text().to_string());
+        second_password_entry.set_text("");
+        let db = Arc::clone(&db);
+        let version = db.read().unwrap().version();
+        let reauth_sender = reauth_sender.clone();
+        std::thread::spawn(move || {
+            let password = appdb::AppDB::derive_key(&raw_password, &raw_second_password, &version);
+            let verified = db.read().unwrap().verify_password_hash(&password).then_some((raw_password, raw_second_password));
+            reauth_sender.send(verified).expect("reauth sender error");
+        });
+    });
+    dialog.show();
+}
+
+
+fn export_dialog<W: gtk::glib::IsA<gtk::Window>>(window: &W, db: Arc<RwLock<appdb::AppDB>>, status_bar: Rc<RefCell<gtk::Statusbar>>) {
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Warning)
+        .buttons(gtk::ButtonsType::OkCancel)
+        .text("Export Unencrypted JSON")
+        .secondary_text("The exported file is NOT encrypted. Anyone who can read it can read everything in it. Delete it as soon as it has been imported elsewhere.")
+        .build();
+    let include_values_check = gtk::CheckButton::builder()
+        .label("Include values (otherwise export key names only)")
+        .build();
+    dialog.message_area().downcast::<gtk::Box>().unwrap().append(&include_values_check);
+    let window = window.clone().upcast::<gtk::Window>();
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        if response != gtk::ResponseType::Ok {
+            return;
+        }
+        let include_values = include_values_check.is_active();
+        let db = Arc::clone(&db);
+        let status_bar = Rc::clone(&status_bar);
+        run_native_chooser(&window, "Export JSON", gtk::FileChooserAction::Save, "Export", |chooser| {
+            chooser.set_current_name("digisafe_export.json");
+        }, move |path| {
+            let msg = db.write().unwrap().export_json(&path, include_values);
+            status_bar.borrow().push(0, &msg);
+        });
+    });
+    dialog.show();
+}
+
+
+fn recovery_dialog<W: gtk::glib::IsA<gtk::Window>>(window: &W, db: Arc<RwLock<appdb::AppDB>>, status_bar: Rc<RefCell<gtk::Statusbar>>) {
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Question)
+        .buttons(gtk::ButtonsType::OkCancel)
+        .text("Create Recovery Drive")
+        .secondary_text("Choose a mounted removable drive. A digisafe-recovery folder is written to it with the encrypted database as currently sealed, a copy of this program and restore instructions. Keep the drive somewhere safe and offline.")
+        .build();
+    let window = window.clone().upcast::<gtk::Window>();
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        if response != gtk::ResponseType::Ok {
+            return;
+        }
+        let db = Arc::clone(&db);
+        let status_bar = Rc::clone(&status_bar);
+        run_native_chooser(&window, "Recovery Drive", gtk::FileChooserAction::SelectFolder, "Write", |chooser| {
+            // udisks mounts removable drives under /run/media/<user>.
+            let media_dir = std::path::PathBuf::from("/run/media").join(std::env::var("USER").unwrap_or_default());
+            if media_dir.is_dir() {
+                let _ = chooser.set_current_folder(Some(&gtk::gio::File::for_path(media_dir)));
+            }
+        }, move |path| {
+            let msg = db.read().unwrap().write_recovery(&path);
+            status_bar.borrow().push(0, &msg);
+        });
+    });
+    dialog.show();
+}
+
+
+fn first_run_dialog<W: gtk::glib::IsA<gtk::Window>>(window: &W, status_bar: Rc<RefCell<gtk::Statusbar>>, then: Rc<dyn Fn()>) {
+    let dialog = gtk::MessageDialog::builder()
+        .transient_for(window)
+        .modal(true)
+        .message_type(gtk::MessageType::Question)
+        .buttons(gtk::ButtonsType::YesNo)
+        .text("Welcome to DigiSafe")
+        .secondary_text("No remote backup is configured, so databases will only be saved on this machine. Configure remote credentials now, or import settings exported from another machine?")
+        .build();
+    dialog.add_button("Import Settings…", gtk::ResponseType::Other(1));
+    let window = window.clone().upcast::<gtk::Window>();
+    dialog.connect_response(move |dialog, response| {
+        dialog.close();
+        // The dialog comes back next start, which is all a failed write costs.
+        if let Err(err) = settings::set("setup_done", "true") {
+            status_bar.borrow().push(0, &format!("settings not saved: {err}"));
+        }
+        if response == gtk::ResponseType::Other(1) {
+            import_settings_dialog(&window, Rc::clone(&status_bar), Rc::clone(&then));
+        } else if response == gtk::ResponseType::Yes {
+            let then = Rc::clone(&then);
+            settings_dialog(&window, Rc::clone(&status_bar)).connect_close_request(move |_| {
+                then();
+                gtk::Inhibit(false)
+            });
+        } else {
+            then();
+        }
+    });
+    dialog.show();
+}
+
+
+fn import_settings_dialog<W: gtk::glib::IsA<gtk::Window>>(window: &W, status_bar: Rc<RefCell<gtk::Statusbar>>, then: Rc<dyn Fn()>) {
+    let chooser = run_native_chooser(window, "Import Settings", gtk::FileChooserAction::Open, "Import", |_| {}, move |path| {
+        let msg = match settings::import(&path) {
+            Ok(()) => "settings imported".to_owned(),
+            Err(err) => format!("settings import failure: {err}"),
+        };
+        status_bar.borrow().push(0, &msg);
+    });
+    // Continue whether the import was chosen or cancelled.
+    chooser.connect_response(move |_, _| {
+        then();
+    });
+}
+
+
+// Native dialogs are not widgets, so the chooser keeps itself alive until it responds.
+fn run_native_chooser<W: gtk::glib::IsA<gtk::Window>>(window: &W, title: &str, action: gtk::FileChooserAction, accept_label: &str, configure: impl FnOnce(&gtk::FileChooserNative), on_file: impl Fn(std::path::PathBuf) + 'static) -> gtk::FileChooserNative {
+    let chooser = gtk::FileChooserNative::new(Some(title), Some(window), action, Some(accept_label), Some("Cancel"));
+    chooser.set_modal(true);
+    configure(&chooser);
+    let chooser_holder = RefCell::new(Some(chooser.clone()));
+    chooser.connect_response(move |chooser, response| {
+        if response == gtk::ResponseType::Accept {
+            if let Some(path) = chooser.file().and_then(|file| file.path()) {
+                on_file(path);
+            }
+        }
+        chooser_holder.replace(None);
+    });
+    chooser.show();
+    chooser
+}
+
+
+fn settings_dialog<W: gtk::glib::IsA<gtk::Window>>(window: &W, status_bar: Rc<RefCell<gtk::Statusbar>>) -> Rc<gtk::Dialog> {
+    let key_id_entry = gtk::Entry::builder()
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .width_request(300)
+        .tooltip_text("Backblaze Key ID")
+        .build();
+    let app_key_entry = gtk::PasswordEntry::builder()
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .width_request(300)
+        .tooltip_text("Backblaze Application Key")
+        .show_peek_icon(true)
+        .build();
+    let dropbox_app_key_entry = gtk::Entry::builder()
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .width_request(300)
+        .tooltip_text("Dropbox App Key")
+        .build();
+    let dropbox_token_entry = gtk::PasswordEntry::builder()
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .width_request(300)
+        .tooltip_text("Dropbox OAuth Refresh Token")
+        .show_peek_icon(true)
+        .build();
+    let proxy_entry = gtk::Entry::builder()
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .width_request(300)
+        .tooltip_text("Proxy URL (e.g. socks5h://127.0.0.1:9050 for Tor; socks5h resolves host names through the proxy)")
+        .text(&settings::get("proxy").unwrap_or_default())
+        .build();
+    let pinned_cert_entry = gtk::Entry::builder()
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .width_request(300)
+        .tooltip_text("Pinned server certificate SHA-256 fingerprint (openssl x509 -noout -fingerprint -sha256), replaces CA validation")
+        .text(&settings::get("pinned_cert").unwrap_or_default())
+        .build();
+    let sync_minutes_spin = gtk::SpinButton::with_range(0.0, 1440.0, 1.0);
+    sync_minutes_spin.set_margin_top(10);
+    sync_minutes_spin.set_margin_bottom(10);
+    sync_minutes_spin.set_margin_start(10);
+    sync_minutes_spin.set_margin_end(10);
+    sync_minutes_spin.set_tooltip_text(Some("Background sync interval in minutes (0 disables)"));
+    sync_minutes_spin.set_value(settings::get("sync_minutes").and_then(|minutes| minutes.parse::<f64>().ok()).unwrap_or(0.0));
+    let privacy_mask_check = gtk::CheckButton::builder()
+        .label("Hide entries while the window is unfocused")
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(10)
+        .margin_end(10)
+        .active(settings::get("privacy_mask").is_some())
+        .build();
+    let save_button = gtk::Button::builder()
+        .label("Validate and Save")
+        .margin_top(10)
+        .margin_bottom(10)
+        .margin_start(20)
+        .margin_end(20)
+        .build();
+    let dialog_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .halign(gtk::Align::Center)
+        .build();
+    key_id_entry.update_property(&[gtk::accessible::Property::Label("Backblaze key ID")]);
+    app_key_entry.update_property(&[gtk::accessible::Property::Label("Backblaze application key")]);
+    dropbox_app_key_entry.update_property(&[gtk::accessible::Property::Label("Dropbox app key")]);
+    dropbox_token_entry.update_property(&[gtk::accessible::Property::Label("Dropbox refresh token")]);
+    proxy_entry.update_property(&[gtk::accessible::Property::Label("Proxy URL")]);
+    pinned_cert_entry.update_property(&[gtk::accessible::Property::Label("Pinned certificate fingerprint")]);
+    sync_minutes_spin.update_property(&[gtk::accessible::Property::Label("Background sync interval in minutes")]);
+    dialog_box.append(&key_id_entry);
+    dialog_box.append(&app_key_entry);
+    dialog_box.append(&dropbox_app_key_entry);
+    dialog_box.append(&dropbox_token_entry);
+    dialog_box.append(&proxy_entry);
+    dialog_box.append(&pinned_cert_entry);
+    dialog_box.append(&sync_minutes_spin);
+    dialog_box.append(&privacy_mask_check);
+    dialog_box.append(&save_button);
+    let dialog = Rc::new(gtk::Dialog::builder()
+        .transient_for(window)
+        .title("Settings")
+        .default_height(100)
+        .default_width(300)
+        .modal(true)
+        .child(&dialog_box)
+        .build());
+    let (settings_sender, settings_receiver) = gtk::glib::MainContext::channel::<String>(gtk::glib::PRIORITY_DEFAULT);
+    let dialog_clone = Rc::clone(&dialog);
+    let save_button2 = save_button.clone();
+    settings_receiver.attach(None, move|msg| {
+        status_bar.borrow().push(0, &msg);
+        save_button2.set_sensitive(true);
+        if msg == "settings saved" {
+            dialog_clone.close();
+        }
+        gtk::glib::Continue(true)
+    });
+    save_button.connect_clicked(move |save_button| {
+        save_button.set_sensitive(false);
+        let key_id = key_id_entry.text().trim().to_string();
+        let app_key = app_key_entry.text().trim().to_string();
+        let dropbox_app_key = dropbox_app_key_entry.text().trim().to_string();
+        let dropbox_token = dropbox_token_entry.text().trim().to_string();
+        let proxy = proxy_entry.text().trim().to_string();
+        let pinned_cert = pinned_cert_entry.text().trim().to_string();
+        let privacy_mask = if privacy_mask_check.is_active() { "true" } else { "" };
+        let sync_minutes = match sync_minutes_spin.value_as_int() {
+            0 => "".to_owned(),
+            minutes => minutes.to_string(),
+        };
+        let settings_sender = settings_sender.clone();
+        std::thread::spawn(move || {
+            let msg = if !proxy.is_empty() && reqwest::Proxy::all(&proxy).is_err() {
+                "proxy invalid".to_owned()
+            } else if !pinned_cert.is_empty() && remote::pinned_fingerprint(&pinned_cert).is_err() {
+                "pinned certificate invalid".to_owned()
+            } else if let Err(err) = settings::set("proxy", &proxy)
+                .and_then(|_| settings::set("pinned_cert", &pinned_cert))
+                .and_then(|_| settings::set("sync_minutes", &sync_minutes))
+                .and_then(|_| settings::set("privacy_mask", privacy_mask)) {
+                format!("settings not saved: {err}")
+            } else {
+                let backblaze = if key_id.is_empty() && app_key.is_empty() {
+                    Ok(())
+                } else {
+                    remote::Backblaze::validate_credentials(&key_id, &app_key)
+                        .map_err(|err| format!("credentials invalid: {err}"))
+                        .and_then(|_| remote::Backblaze::store_credentials(&key_id, &app_key).map_err(|err| format!("credentials not saved: {err}")))
+                };
+                let dropbox = || if dropbox_app_key.is_empty() && dropbox_token.is_empty() {
+                    Ok(())
+                } else {
+                    remote::Dropbox::validate_credentials(&dropbox_app_key, &dropbox_token)
+                        .map_err(|err| format!("Dropbox credentials invalid: {err}"))
+                        .and_then(|_| remote::Dropbox::store_credentials(&dropbox_app_key, &dropbox_token).map_err(|err| format!("Dropbox credentials not saved: {err}")))
+                };
+                match backblaze.and_then(|_| dropbox()) {
+                    Ok(()) => match remote::precedence_note() {
+                        Some(note) => format!("settings saved, {note}"),
+                        None => "settings saved".to_owned(),
+                    },
+                    Err(msg) => msg,
+                }
+            };
+            settings_sender.send(msg).expect("settings sender error");
+        });
+    });
+    dialog.show();
+    dialog
+}
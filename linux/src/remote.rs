@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::settings;
+
+mod backblaze;
+mod dropbox;
+mod gdrive;
+mod git;
+mod rclone;
+
+pub use backblaze::Backblaze;
+pub use dropbox::Dropbox;
+pub use gdrive::GoogleDrive;
+pub use git::Git;
+pub use rclone::Rclone;
+
+pub trait Remote {
+    fn upload(&self, remote_id: &str, db_enc: &str) -> Result<(), String>;
+    fn download(&self, remote_id: &str) -> Result<String, String>;
+    // Deleting a copy that does not exist succeeds.
+    fn delete(&self, remote_id: &str) -> Result<(), String>;
+
+    fn verify(&self, remote_id: &str, db_enc: &str) -> bool {
+        matches!(self.download(remote_id), Ok(remote_enc) if remote_enc == db_enc)
+    }
+}
+
+// In order of precedence; configured() uses the first one set up.
+const BACKENDS: [(&str, &str); 5] = [
+    ("git", git::CONFIG_PATH),
+    ("rclone", rclone::CONFIG_PATH),
+    ("Dropbox", dropbox::CONFIG_PATH),
+    ("Google Drive", gdrive::CONFIG_PATH),
+    ("Backblaze", backblaze::CONFIG_PATH),
+];
+
+pub fn is_configured() -> bool {
+    !configured_names().is_empty()
+}
+
+pub fn configured_names() -> Vec<&'static str> {
+    BACKENDS.iter()
+        .filter(|(_, config_path)| Path::new(config_path).exists())
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+// Only one backend syncs, so say which when credentials for several are present.
+pub fn precedence_note() -> Option<String> {
+    match configured_names().as_slice() {
+        [used, ignored @ ..] if !ignored.is_empty() => Some(format!("syncing with {used}, {} ignored", ignored.join(", "))),
+        _ => None,
+    }
+}
+
+pub fn configured() -> Result<Option<Box<dyn Remote>>, String> {
+    let remote: Box<dyn Remote> = if Path::new(git::CONFIG_PATH).exists() {
+        Box::new(Git::new()?)
+    } else if Path::new(rclone::CONFIG_PATH).exists() {
+        Box::new(Rclone::new()?)
+    } else if Path::new(dropbox::CONFIG_PATH).exists() {
+        Box::new(Dropbox::new()?)
+    } else if Path::new(gdrive::CONFIG_PATH).exists() {
+        Box::new(GoogleDrive::new()?)
+    } else if Path::new(backblaze::CONFIG_PATH).exists() {
+        Box::new(Backblaze::new()?)
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(remote))
+}
+
+fn read_config(config_path: &str) -> Result<HashMap<String, String>, String> {
+    let config_str = std::fs::read_to_string(config_path).map_err(|err| format!("{config_path}: {err}"))?;
+    serde_json::from_str(&config_str).map_err(|err| format!("{config_path}: {err}"))
+}
+
+// Credentials are readable by the owner only. Fields are merged into the existing file, so settings
+// this app does not edit survive; a null field is removed.
+fn write_secret_config(config_path: &str, fields: &serde_json::Value) -> Result<(), String> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut config: serde_json::Map<String, serde_json::Value> = std::fs::read_to_string(config_path).ok()
+        .and_then(|config_str| serde_json::from_str(&config_str).ok())
+        .unwrap_or_default();
+    for (name, value) in fields.as_object().into_iter().flatten() {
+        if value.is_null() {
+            config.remove(name);
+        } else {
+            config.insert(name.to_owned(), value.to_owned());
+        }
+    }
+    let config = serde_json::Value::Object(config);
+    let config_path = Path::new(config_path);
+    std::fs::create_dir_all(config_path.parent().unwrap()).map_err(|err| err.to_string())?;
+    let mut config_file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(config_path)
+        .map_err(|err| err.to_string())?;
+    config_file.write_all(config.to_string().as_bytes()).map_err(|err| err.to_string())
+}
+
+fn config_field(config: &HashMap<String, String>, name: &str) -> Result<String, String> {
+    config.get(name).cloned().ok_or_else(|| format!("remote config has no {name}"))
+}
+
+pub fn http_client() -> Result<reqwest::blocking::Client, String> {
+    let mut builder = reqwest::blocking::Client::builder()
+        .connect_timeout(Duration::from_secs(10))
+        .timeout(Duration::from_secs(60));
+    if let Some(proxy) = settings::get("proxy") {
+        builder = builder.proxy(reqwest::Proxy::all(proxy).map_err(|err| format!("proxy invalid: {err}"))?);
+    }
+    if let Some(pinned_cert) = settings::get("pinned_cert") {
+        let fingerprint = pinned_fingerprint(&pinned_cert).map_err(|err| format!("pinned certificate invalid: {err}"))?;
+        let tls = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(PinnedCertificate(fingerprint)))
+            .with_no_client_auth();
+        builder = builder.use_preconfigured_tls(tls);
+    }
+    builder.build().map_err(|err| err.to_string())
+}
+
+// The SHA-256 fingerprint of the server certificate, in hex with or without the colons openssl prints.
+pub fn pinned_fingerprint(fingerprint: &str) -> Result<[u8; 32], String> {
+    let fingerprint: String = fingerprint.chars().filter(|c| *c != ':').collect();
+    let fingerprint = hex::decode(fingerprint).map_err(|err| err.to_string())?;
+    fingerprint.try_into().map_err(|_| "not a SHA-256 fingerprint".to_owned())
+}
+
+// Accepts only the pinned certificate, whoever issued it, so no CA can stand in for the server.
+// The handshake signature is still checked against the key in that certificate.
+struct PinnedCertificate([u8; 32]);
+
+impl rustls::client::ServerCertVerifier for PinnedCertificate {
+
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        use sha2::Digest;
+        if sha2::Sha256::digest(&end_entity.0).as_slice() == self.0 {
+            Ok(rustls::client::ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General("server certificate does not match the pinned fingerprint".into()))
+        }
+    }
+
+}
@@ -0,0 +1,142 @@
+use sha1::Sha1;
+
+use super::Remote;
+
+pub const CONFIG_PATH: &str = "/secrets/backblaze.json";
+
+pub struct Backblaze {
+    api_key: String,
+}
+
+impl Backblaze {
+
+    pub fn new() -> Result<Self, String> {
+        let api_config = super::read_config(CONFIG_PATH)?;
+        let api_key = base64::encode(format!("{}:{}", super::config_field(&api_config, "key_id")?, super::config_field(&api_config, "app_key")?));
+        Ok(Backblaze {
+            api_key,
+        })
+    }
+
+    pub fn validate_credentials(key_id: &str, app_key: &str) -> Result<(), String> {
+        let api_key = base64::encode(format!("{key_id}:{app_key}"));
+        let auth_url = "https://api.backblazeb2.com/b2api/v2/b2_authorize_account";
+        let b2 = super::http_client()?;
+        let auth_resp = b2.get(auth_url)
+            .header("Authorization", format!("Basic {api_key}"))
+            .send()
+            .map_err(|err| err.to_string())?;
+        if auth_resp.status() == 200 {
+            Ok(())
+        } else {
+            Err(format!("authorization failed ({})", auth_resp.status()))
+        }
+    }
+
+    pub fn store_credentials(key_id: &str, app_key: &str) -> Result<(), String> {
+        super::write_secret_config(CONFIG_PATH, &serde_json::json!({"key_id": key_id, "app_key": app_key}))
+    }
+
+    fn authorize(&self, b2: &reqwest::blocking::Client) -> Result<serde_json::Value, String> {
+        let api_key = &self.api_key;
+        let auth_url = "https://api.backblazeb2.com/b2api/v2/b2_authorize_account";
+        let auth_req = b2.get(auth_url).header("Authorization", format!("Basic {api_key}")).build().unwrap();
+        let auth_resp = b2.execute(auth_req).and_then(|resp| resp.text()).map_err(|err| err.to_string())?;
+        serde_json::from_str(&auth_resp).map_err(|err| err.to_string())
+    }
+
+    fn json_str(value: &serde_json::Value, pointer: &str) -> Result<String, String> {
+        value.pointer(pointer)
+            .and_then(|field| field.as_str())
+            .map(|field| field.to_owned())
+            .ok_or_else(|| format!("missing {pointer} in response"))
+    }
+
+}
+
+impl Remote for Backblaze {
+
+    fn upload(&self, remote_id: &str, db_enc: &str) -> Result<(), String> {
+        use sha1::Digest;
+        let b2 = super::http_client()?;
+        let auth = self.authorize(&b2)?;
+        let auth_token = Backblaze::json_str(&auth, "/authorizationToken")?;
+        let bucket_id = Backblaze::json_str(&auth, "/allowed/bucketId")?;
+        let api_url = Backblaze::json_str(&auth, "/apiUrl")?;
+        let upload_url_req = b2.post(format!("{api_url}/b2api/v2/b2_get_upload_url"))
+            .body(format!("{{\"bucketId\":\"{bucket_id}\"}}"))
+            .header("Authorization", auth_token)
+            .build().unwrap();
+        let upload_url_resp = b2.execute(upload_url_req).and_then(|resp| resp.text()).map_err(|err| err.to_string())?;
+        let upload_url_resp_map: serde_json::Value = serde_json::from_str(&upload_url_resp).map_err(|err| err.to_string())?;
+        let upload_url = Backblaze::json_str(&upload_url_resp_map, "/uploadUrl")?;
+        let upload_token = Backblaze::json_str(&upload_url_resp_map, "/authorizationToken")?;
+        let mut sha1_hasher: Sha1 = Sha1::new();
+        sha1_hasher.update(db_enc.as_bytes());
+        let sha1_hash = hex::encode(sha1_hasher.finalize());
+        let file_path = format!("{}/{}", remote_id, "digisafe.db");
+        // db_enc is already sealed client side, and its version, id and revision header is bound
+        // into the nonce, so provider-side encryption below is defense in depth only.
+        let upload_req = b2.post(upload_url).body(db_enc.to_string())
+            .header("Authorization", upload_token)
+            .header("X-Bz-File-Name", file_path)
+            .header("Content-Type", "text/plain")
+            .header("X-Bz-Content-Sha1", sha1_hash)
+            .header("X-Bz-Info-Author", "DigiSafe")
+            .header("X-Bz-Server-Side-Encryption", "AES256")
+            .build().unwrap();
+        let upload_resp = b2.execute(upload_req).map_err(|err| err.to_string())?;
+        if upload_resp.status() == 200 {
+            Ok(())
+        } else {
+            Err(format!("upload failed ({})", upload_resp.status()))
+        }
+    }
+
+    fn download(&self, remote_id: &str) -> Result<String, String> {
+        let b2 = super::http_client()?;
+        let auth = self.authorize(&b2)?;
+        let auth_token = Backblaze::json_str(&auth, "/authorizationToken")?;
+        let download_url = Backblaze::json_str(&auth, "/downloadUrl")?;
+        let download_req = b2.get(format!("{download_url}/file/digisafe/{remote_id}/digisafe.db"))
+            .header("Authorization", auth_token)
+            .build().unwrap();
+        let download_resp = b2.execute(download_req).map_err(|err| err.to_string())?;
+        match download_resp.status().as_u16() {
+            200 => download_resp.text().map_err(|err| err.to_string()),
+            404 => Ok("".to_owned()),
+            status => Err(format!("download failed ({status})")),
+        }
+    }
+
+    // B2 keeps every uploaded version, so each one has to go.
+    fn delete(&self, remote_id: &str) -> Result<(), String> {
+        let b2 = super::http_client()?;
+        let auth = self.authorize(&b2)?;
+        let auth_token = Backblaze::json_str(&auth, "/authorizationToken")?;
+        let bucket_id = Backblaze::json_str(&auth, "/allowed/bucketId")?;
+        let api_url = Backblaze::json_str(&auth, "/apiUrl")?;
+        let file_path = format!("{}/{}", remote_id, "digisafe.db");
+        let list_req = b2.post(format!("{api_url}/b2api/v2/b2_list_file_versions"))
+            .body(serde_json::json!({"bucketId": bucket_id, "startFileName": file_path, "prefix": file_path, "maxFileCount": 1000}).to_string())
+            .header("Authorization", &auth_token)
+            .build().unwrap();
+        let list_resp = b2.execute(list_req).and_then(|resp| resp.text()).map_err(|err| err.to_string())?;
+        let list_resp_map: serde_json::Value = serde_json::from_str(&list_resp).map_err(|err| err.to_string())?;
+        let versions = list_resp_map["files"].as_array().ok_or("missing /files in response")?;
+        for version in versions {
+            let file_name = Backblaze::json_str(version, "/fileName")?;
+            let file_id = Backblaze::json_str(version, "/fileId")?;
+            let delete_req = b2.post(format!("{api_url}/b2api/v2/b2_delete_file_version"))
+                .body(serde_json::json!({"fileName": file_name, "fileId": file_id}).to_string())
+                .header("Authorization", &auth_token)
+                .build().unwrap();
+            let delete_resp = b2.execute(delete_req).map_err(|err| err.to_string())?;
+            if delete_resp.status() != 200 {
+                return Err(format!("delete failed ({})", delete_resp.status()));
+            }
+        }
+        Ok(())
+    }
+
+}
@@ -0,0 +1,138 @@
+use super::Remote;
+
+pub const CONFIG_PATH: &str = "/secrets/dropbox.json";
+
+pub struct Dropbox {
+    app_key: String,
+    app_secret: Option<String>,
+    refresh_token: String,
+}
+
+impl Dropbox {
+
+    pub fn new() -> Result<Self, String> {
+        let api_config = super::read_config(CONFIG_PATH)?;
+        Ok(Dropbox {
+            app_key: super::config_field(&api_config, "app_key")?,
+            app_secret: api_config.get("app_secret").cloned(),
+            refresh_token: super::config_field(&api_config, "refresh_token")?,
+        })
+    }
+
+    pub fn validate_credentials(app_key: &str, refresh_token: &str) -> Result<(), String> {
+        let dropbox = Dropbox {
+            app_key: app_key.to_owned(),
+            app_secret: None,
+            refresh_token: refresh_token.to_owned(),
+        };
+        dropbox.access_token(&super::http_client()?).map(|_| ())
+    }
+
+    pub fn store_credentials(app_key: &str, refresh_token: &str) -> Result<(), String> {
+        // An app secret added to the file by hand belongs to its app key, so it only survives while the key stays the same.
+        let same_app = super::read_config(CONFIG_PATH).is_ok_and(|config| config.get("app_key").map(|stored| stored.as_str()) == Some(app_key));
+        let mut config = serde_json::json!({"app_key": app_key, "refresh_token": refresh_token});
+        if !same_app {
+            config["app_secret"] = serde_json::Value::Null;
+        }
+        super::write_secret_config(CONFIG_PATH, &config)
+    }
+
+    // Access tokens are short lived, so each operation trades the stored refresh token for a new one.
+    fn access_token(&self, dropbox: &reqwest::blocking::Client) -> Result<String, String> {
+        let mut form = vec![
+            ("grant_type", "refresh_token"),
+            ("refresh_token", self.refresh_token.as_str()),
+            ("client_id", self.app_key.as_str()),
+        ];
+        if let Some(app_secret) = &self.app_secret {
+            form.push(("client_secret", app_secret));
+        }
+        let token_resp = dropbox.post("https://api.dropboxapi.com/oauth2/token")
+            .form(&form)
+            .send()
+            .map_err(|err| err.to_string())?;
+        if token_resp.status() != 200 {
+            return Err(format!("token refresh failed ({})", token_resp.status()));
+        }
+        let token: serde_json::Value = serde_json::from_str(&token_resp.text().map_err(|err| err.to_string())?).map_err(|err| err.to_string())?;
+        token["access_token"].as_str().map(|token| token.to_owned()).ok_or_else(|| "missing access_token in response".into())
+    }
+
+    fn api_arg(remote_id: &str, upload: bool) -> String {
+        let path = format!("/digisafe/{remote_id}/digisafe.db");
+        if upload {
+            serde_json::json!({"path": path, "mode": "overwrite", "mute": true}).to_string()
+        } else {
+            serde_json::json!({"path": path}).to_string()
+        }
+    }
+
+}
+
+impl Remote for Dropbox {
+
+    fn upload(&self, remote_id: &str, db_enc: &str) -> Result<(), String> {
+        let dropbox = super::http_client()?;
+        let access_token = self.access_token(&dropbox)?;
+        let upload_resp = dropbox.post("https://content.dropboxapi.com/2/files/upload")
+            .bearer_auth(access_token)
+            .header("Dropbox-API-Arg", Dropbox::api_arg(remote_id, true))
+            .header("Content-Type", "application/octet-stream")
+            .body(db_enc.to_string())
+            .send()
+            .map_err(|err| err.to_string())?;
+        if upload_resp.status() == 200 {
+            Ok(())
+        } else {
+            Err(format!("upload failed ({})", upload_resp.status()))
+        }
+    }
+
+    fn download(&self, remote_id: &str) -> Result<String, String> {
+        let dropbox = super::http_client()?;
+        let access_token = self.access_token(&dropbox)?;
+        let download_resp = dropbox.post("https://content.dropboxapi.com/2/files/download")
+            .bearer_auth(access_token)
+            .header("Dropbox-API-Arg", Dropbox::api_arg(remote_id, false))
+            .send()
+            .map_err(|err| err.to_string())?;
+        match download_resp.status().as_u16() {
+            200 => download_resp.text().map_err(|err| err.to_string()),
+            409 => {
+                // Dropbox reports a missing path as an endpoint error rather than a 404.
+                let error = download_resp.text().map_err(|err| err.to_string())?;
+                if error.contains("not_found") {
+                    Ok("".to_owned())
+                } else {
+                    Err("download failed (409)".into())
+                }
+            }
+            status => Err(format!("download failed ({status})")),
+        }
+    }
+
+    fn delete(&self, remote_id: &str) -> Result<(), String> {
+        let dropbox = super::http_client()?;
+        let access_token = self.access_token(&dropbox)?;
+        let delete_resp = dropbox.post("https://api.dropboxapi.com/2/files/delete_v2")
+            .bearer_auth(access_token)
+            .header("Content-Type", "application/json")
+            .body(serde_json::json!({"path": format!("/digisafe/{remote_id}")}).to_string())
+            .send()
+            .map_err(|err| err.to_string())?;
+        match delete_resp.status().as_u16() {
+            200 => Ok(()),
+            409 => {
+                let error = delete_resp.text().map_err(|err| err.to_string())?;
+                if error.contains("not_found") {
+                    Ok(())
+                } else {
+                    Err("delete failed (409)".into())
+                }
+            }
+            status => Err(format!("delete failed ({status})")),
+        }
+    }
+
+}
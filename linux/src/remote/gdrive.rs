@@ -0,0 +1,139 @@
+use super::Remote;
+
+pub const CONFIG_PATH: &str = "/secrets/gdrive.json";
+
+// Files live in the hidden per-application folder, which the drive.appdata scope is limited to.
+pub struct GoogleDrive {
+    client_id: String,
+    client_secret: String,
+    refresh_token: String,
+}
+
+impl GoogleDrive {
+
+    pub fn new() -> Result<Self, String> {
+        let api_config = super::read_config(CONFIG_PATH)?;
+        Ok(GoogleDrive {
+            client_id: super::config_field(&api_config, "client_id")?,
+            client_secret: super::config_field(&api_config, "client_secret")?,
+            refresh_token: super::config_field(&api_config, "refresh_token")?,
+        })
+    }
+
+    // Access tokens expire after an hour, so each operation refreshes one.
+    fn access_token(&self, drive: &reqwest::blocking::Client) -> Result<String, String> {
+        let form = [
+            ("grant_type", "refresh_token"),
+            ("refresh_token", self.refresh_token.as_str()),
+            ("client_id", self.client_id.as_str()),
+            ("client_secret", self.client_secret.as_str()),
+        ];
+        let token_resp = drive.post("https://oauth2.googleapis.com/token")
+            .form(&form)
+            .send()
+            .map_err(|err| err.to_string())?;
+        if token_resp.status() != 200 {
+            return Err(format!("token refresh failed ({})", token_resp.status()));
+        }
+        let token = GoogleDrive::json(token_resp)?;
+        token["access_token"].as_str().map(|token| token.to_owned()).ok_or_else(|| "missing access_token in response".into())
+    }
+
+    fn json(resp: reqwest::blocking::Response) -> Result<serde_json::Value, String> {
+        serde_json::from_str(&resp.text().map_err(|err| err.to_string())?).map_err(|err| err.to_string())
+    }
+
+    fn file_name(remote_id: &str) -> String {
+        format!("digisafe_{remote_id}.db")
+    }
+
+    fn find_file(&self, drive: &reqwest::blocking::Client, access_token: &str, remote_id: &str) -> Result<Option<String>, String> {
+        let query = format!("name = '{}' and trashed = false", GoogleDrive::file_name(remote_id));
+        let list_resp = drive.get("https://www.googleapis.com/drive/v3/files")
+            .bearer_auth(access_token)
+            .query(&[("spaces", "appDataFolder"), ("q", query.as_str()), ("fields", "files(id)")])
+            .send()
+            .map_err(|err| err.to_string())?;
+        if list_resp.status() != 200 {
+            return Err(format!("file lookup failed ({})", list_resp.status()));
+        }
+        let files = GoogleDrive::json(list_resp)?;
+        Ok(files.pointer("/files/0/id").and_then(|id| id.as_str()).map(|id| id.to_owned()))
+    }
+
+    fn create_file(&self, drive: &reqwest::blocking::Client, access_token: &str, remote_id: &str) -> Result<String, String> {
+        let metadata = serde_json::json!({"name": GoogleDrive::file_name(remote_id), "parents": ["appDataFolder"]});
+        let create_resp = drive.post("https://www.googleapis.com/drive/v3/files")
+            .bearer_auth(access_token)
+            .header("Content-Type", "application/json")
+            .body(metadata.to_string())
+            .send()
+            .map_err(|err| err.to_string())?;
+        if create_resp.status() != 200 {
+            return Err(format!("file creation failed ({})", create_resp.status()));
+        }
+        let file = GoogleDrive::json(create_resp)?;
+        file["id"].as_str().map(|id| id.to_owned()).ok_or_else(|| "missing id in response".into())
+    }
+
+}
+
+impl Remote for GoogleDrive {
+
+    fn upload(&self, remote_id: &str, db_enc: &str) -> Result<(), String> {
+        let drive = super::http_client()?;
+        let access_token = self.access_token(&drive)?;
+        let file_id = match self.find_file(&drive, &access_token, remote_id)? {
+            Some(file_id) => file_id,
+            None => self.create_file(&drive, &access_token, remote_id)?,
+        };
+        let upload_resp = drive.patch(format!("https://www.googleapis.com/upload/drive/v3/files/{file_id}"))
+            .bearer_auth(&access_token)
+            .query(&[("uploadType", "media")])
+            .header("Content-Type", "application/octet-stream")
+            .body(db_enc.to_string())
+            .send()
+            .map_err(|err| err.to_string())?;
+        if upload_resp.status() == 200 {
+            Ok(())
+        } else {
+            Err(format!("upload failed ({})", upload_resp.status()))
+        }
+    }
+
+    fn download(&self, remote_id: &str) -> Result<String, String> {
+        let drive = super::http_client()?;
+        let access_token = self.access_token(&drive)?;
+        let file_id = match self.find_file(&drive, &access_token, remote_id)? {
+            Some(file_id) => file_id,
+            None => return Ok("".to_owned()),
+        };
+        let download_resp = drive.get(format!("https://www.googleapis.com/drive/v3/files/{file_id}"))
+            .bearer_auth(&access_token)
+            .query(&[("alt", "media")])
+            .send()
+            .map_err(|err| err.to_string())?;
+        match download_resp.status().as_u16() {
+            200 => download_resp.text().map_err(|err| err.to_string()),
+            status => Err(format!("download failed ({status})")),
+        }
+    }
+
+    fn delete(&self, remote_id: &str) -> Result<(), String> {
+        let drive = super::http_client()?;
+        let access_token = self.access_token(&drive)?;
+        let file_id = match self.find_file(&drive, &access_token, remote_id)? {
+            Some(file_id) => file_id,
+            None => return Ok(()),
+        };
+        let delete_resp = drive.delete(format!("https://www.googleapis.com/drive/v3/files/{file_id}"))
+            .bearer_auth(&access_token)
+            .send()
+            .map_err(|err| err.to_string())?;
+        match delete_resp.status().as_u16() {
+            204 | 404 => Ok(()),
+            status => Err(format!("delete failed ({status})")),
+        }
+    }
+
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use super::Remote;
+
+pub const CONFIG_PATH: &str = "/secrets/git.json";
+
+// The app commits and resets in a clone of its own, so a repository the user works in is only read for its url.
+const CLONE_PATH: &str = "git_clone";
+const REMOTE: &str = "origin";
+
+pub struct Git {
+    repo: PathBuf,
+    url: String,
+    branch: String,
+}
+
+impl Git {
+
+    pub fn new() -> Result<Self, String> {
+        let config = super::read_config(CONFIG_PATH)?;
+        let url = match config.get("url") {
+            Some(url) => url.to_owned(),
+            None => Git::remote_url(Path::new(&super::config_field(&config, "repo")?), config.get("remote").map_or(REMOTE, |remote| remote.as_str()))?,
+        };
+        let git = Git {
+            repo: PathBuf::from(CLONE_PATH),
+            url,
+            branch: config.get("branch").cloned().unwrap_or_else(|| "main".to_owned()),
+        };
+        git.init()?;
+        Ok(git)
+    }
+
+    fn remote_url(repo: &Path, remote: &str) -> Result<String, String> {
+        let remote_url = Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(["remote", "get-url", remote])
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|err| err.to_string())?;
+        if !remote_url.status.success() {
+            return Err(format!("{} has no remote {remote}", repo.display()));
+        }
+        Ok(String::from_utf8_lossy(&remote_url.stdout).trim().to_owned())
+    }
+
+    fn init(&self) -> Result<(), String> {
+        if !self.repo.join(".git").exists() {
+            let initialized = Command::new("git")
+                .args(["init", "--quiet"])
+                .arg(&self.repo)
+                .status()
+                .map_err(|err| err.to_string())?;
+            if !initialized.success() || !self.git(&["remote", "add", REMOTE, &self.url])? {
+                return Err("git init failed".into());
+            }
+        }
+        // Follow the configured url, which an imported or edited config may have changed.
+        if !self.git(&["remote", "set-url", REMOTE, &self.url])? {
+            return Err("git remote set-url failed".into());
+        }
+        if !self.git(&["config", "user.email"])? {
+            self.git(&["config", "user.name", "digisafe"])?;
+            self.git(&["config", "user.email", "digisafe@localhost"])?;
+        }
+        Ok(())
+    }
+
+    pub fn config() -> Option<HashMap<String, String>> {
+        serde_json::from_str(&std::fs::read_to_string(CONFIG_PATH).ok()?).ok()
+    }
+
+    pub fn store_config(config: &HashMap<String, String>) -> Result<(), String> {
+        if !config.contains_key("url") && !config.contains_key("repo") {
+            return Err("git config has no url or repo".into());
+        }
+        let config_path = Path::new(CONFIG_PATH);
+        std::fs::create_dir_all(config_path.parent().unwrap()).map_err(|err| err.to_string())?;
+        std::fs::write(config_path, serde_json::to_string(config).unwrap()).map_err(|err| err.to_string())
+    }
+
+    pub fn is_work_tree(repo: &Path) -> bool {
+        Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(["rev-parse", "--is-inside-work-tree"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    }
+
+    fn db_file(&self, remote_id: &str) -> PathBuf {
+        PathBuf::from(remote_id).join("digisafe.db")
+    }
+
+    // Unlock and save wait on git, so it must never prompt, and stalled transfers have to give up.
+    fn command(&self) -> Command {
+        let mut command = Command::new("git");
+        command.arg("-C")
+            .arg(&self.repo)
+            .args(["-c", "http.lowSpeedLimit=1000", "-c", "http.lowSpeedTime=30"])
+            .env("GIT_TERMINAL_PROMPT", "0");
+        if std::env::var_os("GIT_SSH_COMMAND").is_none() {
+            command.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes -o ConnectTimeout=10 -o ServerAliveInterval=10 -o ServerAliveCountMax=3");
+        }
+        command
+    }
+
+    fn git(&self, args: &[&str]) -> Result<bool, String> {
+        let status = self.command()
+            .args(args)
+            .stdout(Stdio::null())
+            .status()
+            .map_err(|err| err.to_string())?;
+        Ok(status.success())
+    }
+
+    // A new, empty repository has no branch yet, which is not an error.
+    fn fetch(&self) -> Result<bool, String> {
+        if !self.git(&["fetch", "--quiet", "--prune", REMOTE])? {
+            return Err("git fetch failed".into());
+        }
+        self.git(&["rev-parse", "--quiet", "--verify", &self.remote_branch()])
+    }
+
+    fn remote_branch(&self) -> String {
+        format!("{REMOTE}/{}", self.branch)
+    }
+
+    // The file is always replaced wholesale and AppDB does the merging, so local commits are never worth keeping.
+    fn reset(&self) -> Result<(), String> {
+        let reset = if self.fetch()? {
+            self.git(&["checkout", "--quiet", "--force", "-B", &self.branch, &self.remote_branch()])?
+        } else {
+            // Start the branch over so the first push creates it.
+            self.git(&["symbolic-ref", "HEAD", &format!("refs/heads/{}", self.branch)])?
+                && self.git(&["update-ref", "-d", "HEAD"])?
+                && self.git(&["read-tree", "--empty"])?
+        };
+        if reset {
+            Ok(())
+        } else {
+            Err("git reset failed".into())
+        }
+    }
+
+    fn push(&self) -> Result<(), String> {
+        if self.git(&["push", "--quiet", REMOTE, &format!("HEAD:refs/heads/{}", self.branch)])? {
+            Ok(())
+        } else {
+            // Drop the rejected commit so the clone keeps following the remote.
+            self.reset()?;
+            Err("git push failed".into())
+        }
+    }
+
+    // Reads the file as last fetched from the remote, never from the working tree.
+    fn show(&self, remote_id: &str) -> Result<Option<Vec<u8>>, String> {
+        let object = format!("{}:{}", self.remote_branch(), self.db_file(remote_id).to_str().unwrap());
+        let shown = self.command()
+            .args(["show", &object])
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|err| err.to_string())?;
+        Ok(shown.status.success().then_some(shown.stdout))
+    }
+
+}
+
+impl Remote for Git {
+
+    fn upload(&self, remote_id: &str, db_enc: &str) -> Result<(), String> {
+        self.reset()?;
+        let db_file = self.db_file(remote_id);
+        let db_path = self.repo.join(&db_file);
+        std::fs::create_dir_all(db_path.parent().unwrap()).map_err(|err| err.to_string())?;
+        std::fs::write(&db_path, db_enc).map_err(|err| err.to_string())?;
+        let db_file = db_file.to_str().unwrap();
+        if !self.git(&["add", db_file])? {
+            return Err("git add failed".into());
+        }
+        let unchanged = self.git(&["diff", "--cached", "--quiet", "--", db_file])?;
+        if !unchanged {
+            let message = format!("digisafe {} revision {}", remote_id, &db_enc[16..24]);
+            if !self.git(&["commit", "--quiet", "-m", &message, "--", db_file])? {
+                return Err("git commit failed".into());
+            }
+        }
+        self.push()
+    }
+
+    // Older commits still hold the file; only rewriting the repository history removes it from there.
+    fn delete(&self, remote_id: &str) -> Result<(), String> {
+        self.reset()?;
+        if self.show(remote_id)?.is_none() {
+            return Ok(());
+        }
+        let db_file = self.db_file(remote_id);
+        let db_file = db_file.to_str().unwrap();
+        if !self.git(&["rm", "--quiet", db_file])? {
+            return Err("git rm failed".into());
+        }
+        if !self.git(&["commit", "--quiet", "-m", &format!("digisafe remove {remote_id}"), "--", db_file])? {
+            return Err("git commit failed".into());
+        }
+        self.push()
+    }
+
+    fn download(&self, remote_id: &str) -> Result<String, String> {
+        if !self.fetch()? {
+            return Ok("".to_owned());
+        }
+        match self.show(remote_id)? {
+            Some(db_enc) => String::from_utf8(db_enc).map_err(|err| err.to_string()),
+            None => Ok("".to_owned()),
+        }
+    }
+
+    fn verify(&self, remote_id: &str, db_enc: &str) -> bool {
+        self.fetch().unwrap_or(false) && matches!(self.show(remote_id), Ok(Some(shown)) if shown == db_enc.as_bytes())
+    }
+
+}
@@ -0,0 +1,104 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use sha2::{Digest, Sha256};
+
+use super::Remote;
+
+pub const CONFIG_PATH: &str = "/secrets/rclone.json";
+
+// rclone exit codes for a missing directory or file.
+const NOT_FOUND_CODES: [i32; 2] = [3, 4];
+
+pub struct Rclone {
+    remote: String,
+    config: Option<String>,
+}
+
+impl Rclone {
+
+    pub fn new() -> Result<Self, String> {
+        let config = super::read_config(CONFIG_PATH)?;
+        Ok(Rclone {
+            remote: super::config_field(&config, "remote")?.trim_end_matches('/').to_owned(),
+            config: config.get("config").cloned(),
+        })
+    }
+
+    fn db_file(&self, remote_id: &str) -> String {
+        format!("{}/{}/digisafe.db", self.remote, remote_id)
+    }
+
+    fn rclone(&self, args: &[&str]) -> Command {
+        let mut command = Command::new("rclone");
+        // Unlock and save wait on rclone, so fail fast rather than retry a stalled endpoint or prompt for a config password.
+        command.args(["--contimeout", "10s", "--timeout", "30s", "--retries", "1", "--low-level-retries", "1", "--ask-password=false"]);
+        if let Some(config) = &self.config {
+            command.arg("--config").arg(config);
+        }
+        command.args(args);
+        command
+    }
+
+    fn remote_sha256(&self, remote_id: &str) -> Result<String, String> {
+        // --download hashes the stored bytes even on providers without native SHA-256 support.
+        let hashed = self.rclone(&["hashsum", "sha256", "--download", &self.db_file(remote_id)])
+            .output()
+            .map_err(|err| err.to_string())?;
+        if !hashed.status.success() {
+            return Err("rclone hashsum failed".into());
+        }
+        let hashed = String::from_utf8_lossy(&hashed.stdout);
+        Ok(hashed.split_whitespace().next().unwrap_or_default().to_lowercase())
+    }
+
+}
+
+impl Remote for Rclone {
+
+    fn upload(&self, remote_id: &str, db_enc: &str) -> Result<(), String> {
+        let mut rcat = self.rclone(&["rcat", &self.db_file(remote_id)])
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| err.to_string())?;
+        let written = rcat.stdin.take().unwrap().write_all(db_enc.as_bytes());
+        // Closing stdin ends the upload. Wait even after a failed write, so rclone is reaped and explains why.
+        let rcat = rcat.wait_with_output().map_err(|err| err.to_string())?;
+        if !rcat.status.success() {
+            return Err(format!("rclone rcat failed: {}", String::from_utf8_lossy(&rcat.stderr).trim()));
+        }
+        // The checksum comparison is left to verify, which the save runs next.
+        written.map_err(|err| err.to_string())
+    }
+
+    fn download(&self, remote_id: &str) -> Result<String, String> {
+        let fetched = self.rclone(&["cat", &self.db_file(remote_id)])
+            .output()
+            .map_err(|err| err.to_string())?;
+        if fetched.status.success() {
+            String::from_utf8(fetched.stdout).map_err(|err| err.to_string())
+        } else if fetched.status.code().is_some_and(|code| NOT_FOUND_CODES.contains(&code)) {
+            Ok("".to_owned())
+        } else {
+            Err("rclone cat failed".into())
+        }
+    }
+
+    fn delete(&self, remote_id: &str) -> Result<(), String> {
+        let deleted = self.rclone(&["deletefile", &self.db_file(remote_id)])
+            .output()
+            .map_err(|err| err.to_string())?;
+        if !deleted.status.success() && !deleted.status.code().is_some_and(|code| NOT_FOUND_CODES.contains(&code)) {
+            return Err("rclone deletefile failed".into());
+        }
+        // Directory-less backends have nothing to remove here.
+        let _ = self.rclone(&["rmdir", &format!("{}/{}", self.remote, remote_id)]).output();
+        Ok(())
+    }
+
+    fn verify(&self, remote_id: &str, db_enc: &str) -> bool {
+        matches!(self.remote_sha256(remote_id), Ok(remote_sha256) if remote_sha256 == hex::encode(Sha256::digest(db_enc.as_bytes())))
+    }
+
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::remote::{self, Git};
+
+pub const SETTINGS_PATH: &str = "digisafe.json";
+
+// Revision tracking, sync direction and window geometry belong to this device, so only these preferences travel.
+const PORTABLE_KEYS: [&str; 4] = ["proxy", "pinned_cert", "sync_minutes", "privacy_mask"];
+
+pub fn load() -> HashMap<String, String> {
+    std::fs::read_to_string(SETTINGS_PATH)
+        .ok()
+        .and_then(|settings_str| serde_json::from_str(&settings_str).ok())
+        .unwrap_or_default()
+}
+
+pub fn get(key: &str) -> Option<String> {
+    load().remove(key)
+}
+
+pub fn set(key: &str, value: &str) -> Result<(), String> {
+    let mut settings = load();
+    if value.is_empty() {
+        settings.remove(key);
+    } else {
+        settings.insert(key.to_owned(), value.to_owned());
+    }
+    let settings_str = serde_json::to_string_pretty(&settings).unwrap();
+    std::fs::write(SETTINGS_PATH, settings_str).map_err(|err| err.to_string())
+}
+
+pub fn export(path: &Path) -> Result<(), String> {
+    let mut settings = load();
+    settings.retain(|key, _| PORTABLE_KEYS.contains(&key.as_str()));
+    let portable = serde_json::json!({"settings": settings, "git": Git::config()});
+    std::fs::write(path, serde_json::to_string_pretty(&portable).unwrap()).map_err(|err| err.to_string())
+}
+
+pub fn import(path: &Path) -> Result<(), String> {
+    let portable_str = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let portable: serde_json::Value = serde_json::from_str(&portable_str).map_err(|err| err.to_string())?;
+    let mut imported: HashMap<String, String> = serde_json::from_value(portable["settings"].clone()).map_err(|err| err.to_string())?;
+    imported.retain(|key, _| PORTABLE_KEYS.contains(&key.as_str()));
+    // Reject what the http client cannot use.
+    if let Some(proxy) = imported.get("proxy").filter(|proxy| !proxy.is_empty()) {
+        reqwest::Proxy::all(proxy).map_err(|err| format!("proxy invalid: {err}"))?;
+    }
+    if let Some(pinned_cert) = imported.get("pinned_cert").filter(|pinned_cert| !pinned_cert.is_empty()) {
+        remote::pinned_fingerprint(pinned_cert).map_err(|err| format!("pinned certificate invalid: {err}"))?;
+    }
+    if let Some(git_config) = portable["git"].as_object() {
+        let git_config: HashMap<String, String> = git_config.iter().filter_map(|(key, value)| Some((key.to_owned(), value.as_str()?.to_owned()))).collect();
+        // A repo is a path on the other machine; keep this machine's remote unless there is a url or the same clone exists here.
+        if git_config.contains_key("url") || git_config.get("repo").is_some_and(|repo| Git::is_work_tree(Path::new(repo))) {
+            Git::store_config(&git_config)?;
+        }
+    }
+    let mut settings = load();
+    settings.extend(imported);
+    let settings_str = serde_json::to_string_pretty(&settings).unwrap();
+    std::fs::write(SETTINGS_PATH, settings_str).map_err(|err| err.to_string())
+}
@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::remote::{self, Remote};
+
+// Everything AppDB persists goes through here: its own files on this machine and the remote it syncs with.
+pub trait Store: Send + Sync {
+    fn read(&self, path: &Path) -> std::io::Result<String>;
+    // Creates missing directories and returns once the contents are on disk.
+    fn write(&self, path: &Path, contents: &str) -> std::io::Result<()>;
+    // Replaces the destination in one step and returns once the rename is on disk.
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()>;
+    fn remove(&self, path: &Path) -> std::io::Result<()>;
+    fn exists(&self, path: &Path) -> bool;
+    fn modified(&self, path: &Path) -> Option<SystemTime>;
+    // Files directly inside dir; a missing dir lists nothing.
+    fn list(&self, dir: &Path) -> Vec<PathBuf>;
+    fn has_remote(&self) -> bool;
+    fn remote(&self) -> Result<Option<Box<dyn Remote>>, String>;
+}
+
+pub struct LocalFiles;
+
+impl LocalFiles {
+
+    fn parent(path: &Path) -> &Path {
+        path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."))
+    }
+
+}
+
+impl Store for LocalFiles {
+
+    fn read(&self, path: &Path) -> std::io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+
+    fn write(&self, path: &Path, contents: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        std::fs::create_dir_all(LocalFiles::parent(path))?;
+        let mut file = std::fs::File::create(path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        std::fs::rename(from, to)?;
+        std::fs::File::open(LocalFiles::parent(to))?.sync_all()
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    fn list(&self, dir: &Path) -> Vec<PathBuf> {
+        std::fs::read_dir(dir).into_iter().flatten().flatten().map(|dir_entry| dir_entry.path()).collect()
+    }
+
+    fn has_remote(&self) -> bool {
+        remote::is_configured()
+    }
+
+    fn remote(&self) -> Result<Option<Box<dyn Remote>>, String> {
+        remote::configured()
+    }
+
+}
+
+// Keeps files in memory and has no remote, so tests never touch this machine's databases or backups.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct MemoryFiles {
+    files: std::sync::Arc<std::sync::Mutex<std::collections::BTreeMap<PathBuf, (String, SystemTime)>>>,
+}
+
+#[cfg(test)]
+impl Store for MemoryFiles {
+
+    fn read(&self, path: &Path) -> std::io::Result<String> {
+        let files = self.files.lock().unwrap();
+        files.get(path).map(|(contents, _)| contents.to_owned()).ok_or_else(|| std::io::ErrorKind::NotFound.into())
+    }
+
+    fn write(&self, path: &Path, contents: &str) -> std::io::Result<()> {
+        self.files.lock().unwrap().insert(path.to_owned(), (contents.to_owned(), SystemTime::now()));
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        let mut files = self.files.lock().unwrap();
+        let file = files.remove(from).ok_or(std::io::ErrorKind::NotFound)?;
+        files.insert(to.to_owned(), file);
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        self.files.lock().unwrap().remove(path).map(|_| ()).ok_or_else(|| std::io::ErrorKind::NotFound.into())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.files.lock().unwrap().contains_key(path)
+    }
+
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        self.files.lock().unwrap().get(path).map(|(_, modified)| *modified)
+    }
+
+    fn list(&self, dir: &Path) -> Vec<PathBuf> {
+        let dir = if dir == Path::new(".") { Path::new("") } else { dir };
+        self.files.lock().unwrap().keys().filter(|path| path.parent() == Some(dir)).cloned().collect()
+    }
+
+    fn has_remote(&self) -> bool {
+        false
+    }
+
+    fn remote(&self) -> Result<Option<Box<dyn Remote>>, String> {
+        Ok(None)
+    }
+
+}